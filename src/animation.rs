@@ -0,0 +1,225 @@
+// Rendering a scene's timeline frame by frame, to numbered images and optionally a video.
+
+use std::{
+    fmt, io,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+};
+
+use image::RgbaImage;
+
+use crate::{aov, offline, scene::Scene};
+
+#[derive(Debug)]
+pub enum Error {
+    Render(offline::Error),
+    Aovs(exr::error::Error),
+    /// `ffmpeg` couldn't be started, or stopped taking frames
+    Encoder(io::Error),
+    EncoderFailed(ExitStatus),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Render(error) => write!(f, "{error}"),
+            Self::Aovs(error) => write!(f, "failed to write AOVs: {error}"),
+            Self::Encoder(error) => write!(f, "failed to run ffmpeg: {error}"),
+            Self::EncoderFailed(status) => write!(f, "ffmpeg failed: {status}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Where the frames go. At least one of the two should be set.
+pub struct Output<'a> {
+    /// Image file name for each frame, numbered as in `frame_path`
+    pub frames: Option<&'a Path>,
+    /// Video file to encode the frames into with `ffmpeg`
+    pub video: Option<&'a Path>,
+    /// Whether to write AOVs as layers of each frame's OpenEXR file
+    pub aovs: bool,
+}
+
+/// Renders every frame of the scene's timeline at its frame rate, with full quality settings and
+/// motion blur.
+pub fn render(
+    renderer: &offline::OfflineRenderer,
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    output: Output<'_>,
+) -> Result<(), Error> {
+    let timeline = &scene.timeline;
+    let count = ((timeline.duration * timeline.frame_rate).round() as u32).max(1);
+
+    let mut encoder = output
+        .video
+        .map(|path| Encoder::new(path, width, height, timeline.frame_rate))
+        .transpose()?;
+
+    let mut frame_scene = scene.clone();
+    for frame in 0..count {
+        eprintln!("Rendering frame {}/{count}", frame + 1);
+
+        let time = frame as f32 / timeline.frame_rate;
+        frame_scene.seek(time);
+        let aovs = output.aovs && output.frames.is_some();
+        let render = renderer
+            .render_frame(scene, time, width, height, aovs)
+            .map_err(Error::Render)?;
+        let image = &render.image;
+
+        let tone_mapping = frame_scene.display_tone_mapping();
+        let look = frame_scene.display_look();
+        if let Some(pattern) = output.frames {
+            let path = frame_path(pattern, frame, count);
+            match &render.aovs {
+                Some(aovs) => aov::save(&path, image, aovs).map_err(Error::Aovs)?,
+                None => offline::save(image, &path, &frame_scene).map_err(Error::Render)?,
+            }
+        }
+        if let Some(encoder) = &mut encoder {
+            encoder.write(&offline::to_display(image, &tone_mapping, &look))?;
+        }
+    }
+
+    encoder.map_or(Ok(()), Encoder::finish)
+}
+
+/// File name for a frame, counting from 1. The last run of `#` in the file name is replaced by
+/// the frame number padded to its length, and without one the number goes before the extension.
+pub fn frame_path(pattern: &Path, frame: u32, count: u32) -> PathBuf {
+    let name = pattern
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let number = frame + 1;
+    let digits = count.to_string().len();
+
+    let name = match name.rfind('#') {
+        Some(end) => {
+            let start = name[..end].trim_end_matches('#').len();
+            let width = (end + 1 - start).max(digits);
+            format!("{}{number:0width$}{}", &name[..start], &name[end + 1..])
+        }
+        None => {
+            let width = digits.max(4);
+            match name.rfind('.') {
+                Some(dot) if dot > 0 => {
+                    format!("{}_{number:0width$}{}", &name[..dot], &name[dot..])
+                }
+                _ => format!("{name}_{number:0width$}"),
+            }
+        }
+    };
+
+    pattern.with_file_name(name)
+}
+
+/// An `ffmpeg` process taking raw RGBA frames on its standard input.
+struct Encoder {
+    process: Child,
+    input: ChildStdin,
+}
+
+impl Encoder {
+    fn new(path: &Path, width: u32, height: u32, frame_rate: f32) -> Result<Self, Error> {
+        let is_webm = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("webm"));
+        let codec: &[&str] = if is_webm {
+            &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "30"]
+        } else {
+            // H.264 in 4:2:0 wants even dimensions, so odd sizes get a row or column of padding
+            &[
+                "-c:v",
+                "libx264",
+                "-crf",
+                "18",
+                "-pix_fmt",
+                "yuv420p",
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            ]
+        };
+
+        let mut process = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{width}x{height}")])
+            .args(["-r", &frame_rate.to_string()])
+            .args(["-i", "-"])
+            .args(codec)
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(Error::Encoder)?;
+        let input = process.stdin.take().expect("ffmpeg stdin is piped");
+
+        Ok(Self { process, input })
+    }
+
+    fn write(&mut self, frame: &RgbaImage) -> Result<(), Error> {
+        match self.input.write_all(frame.as_raw()) {
+            Ok(()) => Ok(()),
+            // ffmpeg closes its input when it gives up, and how it exited says more about why
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {
+                let status = self.process.wait().map_err(Error::Encoder)?;
+                Err(Error::EncoderFailed(status))
+            }
+            Err(error) => Err(Error::Encoder(error)),
+        }
+    }
+
+    /// Closes the input and waits for the video to be written.
+    fn finish(self) -> Result<(), Error> {
+        let Self { mut process, input } = self;
+        drop(input);
+
+        let status = process.wait().map_err(Error::Encoder)?;
+        if !status.success() {
+            return Err(Error::EncoderFailed(status));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pattern: &str, frame: u32, count: u32) -> PathBuf {
+        frame_path(Path::new(pattern), frame, count)
+    }
+
+    #[test]
+    fn replaces_the_last_run_of_hashes() {
+        assert_eq!(frame("out/f###.png", 0, 10), Path::new("out/f001.png"));
+        assert_eq!(frame("a#b##.png", 41, 100), Path::new("a#b042.png"));
+    }
+
+    #[test]
+    fn pads_to_the_frame_count() {
+        assert_eq!(frame("f#.png", 6, 1000), Path::new("f0007.png"));
+        assert_eq!(frame("f.png", 6, 100_000), Path::new("f_000007.png"));
+    }
+
+    #[test]
+    fn numbers_before_the_extension() {
+        assert_eq!(
+            frame("out/shot.png", 11, 24),
+            Path::new("out/shot_0012.png")
+        );
+        assert_eq!(frame("shot.tar.exr", 0, 1), Path::new("shot.tar_0001.exr"));
+    }
+
+    #[test]
+    fn numbers_names_without_an_extension() {
+        assert_eq!(frame("shot", 2, 3), Path::new("shot_0003"));
+        assert_eq!(frame(".hidden", 0, 1), Path::new(".hidden_0001"));
+    }
+}
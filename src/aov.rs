@@ -0,0 +1,111 @@
+// Surface attributes rendered alongside the image for compositing, written as extra layers of an
+// OpenEXR file.
+
+use std::path::Path;
+
+use exr::prelude::*;
+use glam::Vec3;
+use image::Rgba32FImage;
+
+/// Per pixel surface attributes, averaged over the samples that hit the fractal. Pixels nothing
+/// was hit in are zero, apart from their depth.
+pub struct Aovs {
+    pub width: u32,
+    pub height: u32,
+    /// Distance to the image plane, or to the camera for projections without one. Infinite where
+    /// nothing was hit
+    pub depth: Vec<f32>,
+    pub position: Vec<Vec3>,
+    pub normal: Vec<Vec3>,
+    /// Ambient occlusion, from 0 for fully occluded to 1 for open
+    pub occlusion: Vec<f32>,
+    pub orbit_trap: Vec<f32>,
+    /// Distance estimator iterations at the surface
+    pub iterations: Vec<f32>,
+    /// Fraction of the pixel's samples that hit the fractal
+    pub alpha: Vec<f32>,
+}
+
+impl Aovs {
+    /// Averages the sums read back from the AOV buffer, laid out as the shader's `Aov` for every
+    /// pixel from the top left.
+    pub fn from_sums(width: u32, height: u32, sums: &[f32]) -> Self {
+        let pixels = (width * height) as usize;
+        let mut aovs = Self {
+            width,
+            height,
+            depth: Vec::with_capacity(pixels),
+            position: Vec::with_capacity(pixels),
+            normal: Vec::with_capacity(pixels),
+            occlusion: Vec::with_capacity(pixels),
+            orbit_trap: Vec::with_capacity(pixels),
+            iterations: Vec::with_capacity(pixels),
+            alpha: Vec::with_capacity(pixels),
+        };
+
+        for sum in sums.chunks_exact(12).take(pixels) {
+            let [px, py, pz, depth, nx, ny, nz, occlusion, trap, iterations, hits, samples] =
+                sum.try_into().expect("chunks of 12");
+            let scale = if hits > 0.0 { 1.0 / hits } else { 0.0 };
+
+            aovs.depth.push(if hits > 0.0 {
+                depth * scale
+            } else {
+                f32::INFINITY
+            });
+            aovs.position.push(Vec3::new(px, py, pz) * scale);
+            aovs.normal
+                .push((Vec3::new(nx, ny, nz) * scale).normalize_or_zero());
+            aovs.occlusion.push(occlusion * scale);
+            aovs.orbit_trap.push(trap * scale);
+            aovs.iterations.push(iterations * scale);
+            aovs.alpha.push(hits / samples.max(1.0));
+        }
+
+        aovs
+    }
+}
+
+/// Writes the image and its AOVs as one 32-bit float OpenEXR file. The image keeps the usual
+/// R, G, B and A channels, and each AOV gets its own layer, like `depth.Z` and `normal.X`.
+pub fn save(path: &Path, image: &Rgba32FImage, aovs: &Aovs) -> Result<()> {
+    let channel = |name: &str, samples: Vec<f32>| AnyChannel::new(name, FlatSamples::F32(samples));
+    let image_channel = |index: usize| {
+        image
+            .pixels()
+            .map(|pixel| pixel.0[index])
+            .collect::<Vec<_>>()
+    };
+    let component = |vectors: &[Vec3], index: usize| {
+        vectors
+            .iter()
+            .map(|vector| vector[index])
+            .collect::<Vec<_>>()
+    };
+
+    let channels = vec![
+        channel("R", image_channel(0)),
+        channel("G", image_channel(1)),
+        channel("B", image_channel(2)),
+        channel("A", image_channel(3)),
+        channel("depth.Z", aovs.depth.clone()),
+        channel("position.X", component(&aovs.position, 0)),
+        channel("position.Y", component(&aovs.position, 1)),
+        channel("position.Z", component(&aovs.position, 2)),
+        channel("normal.X", component(&aovs.normal, 0)),
+        channel("normal.Y", component(&aovs.normal, 1)),
+        channel("normal.Z", component(&aovs.normal, 2)),
+        channel("occlusion.Y", aovs.occlusion.clone()),
+        channel("orbit_trap.Y", aovs.orbit_trap.clone()),
+        channel("iterations.Y", aovs.iterations.clone()),
+        channel("alpha.A", aovs.alpha.clone()),
+    ];
+
+    let layer = Layer::new(
+        (aovs.width as usize, aovs.height as usize),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+    Image::from_layer(layer).write().to_file(path)
+}
@@ -1,21 +1,236 @@
-use iced::{widget::column, Length, Sandbox};
+use std::{path::PathBuf, time::Instant};
 
-use crate::shader::program::ShaderProgram;
+use glam::Vec3;
+use iced::{
+    event, executor, keyboard, theme,
+    widget::{
+        button, checkbox, column, container, image, pick_list, row, scrollable, slider, text,
+        text_input, Column, Row,
+    },
+    window, Alignment, Application, Command, Element, Event, Length, Subscription, Theme,
+};
 
-#[derive(Debug)]
-pub enum Message {}
+use crate::{
+    bookmark::{Bookmark, Transition},
+    camera,
+    fractal::Fractal,
+    history::History,
+    marcher::RELAXATION_RANGE,
+    post::{Effect, PostEffect},
+    presets::{self, PRESETS},
+    render_mode::RenderMode,
+    scene::Scene,
+    sdf,
+    shader::program::ShaderProgram,
+    stereo::StereoMode,
+    timeline::{Interpolation, Parameter},
+    tone_mapping::ToneMapper,
+    vec_input::{self, VecInput},
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    FractalSelected(Fractal),
+    RenderModeSelected(RenderMode),
+    StereoModeSelected(StereoMode),
+    ToneMapperSelected(ToneMapper),
+    ExposureChanged(f32),
+    LutPathChanged(String),
+    LutLoaded,
+    LutStrengthChanged(f32),
+    ScenePathChanged(String),
+    SceneLoaded,
+    /// A scene file or rendered PNG was dropped on the window
+    FileDropped(PathBuf),
+    SceneSaved,
+    PresetsToggled,
+    ThumbnailsRendered(Vec<Option<PathBuf>>),
+    PresetSelected(usize),
+    Undo,
+    Redo,
+    HistoryToggled,
+    HistorySelected(usize),
+    /// The view started being dragged or flown, an edit that lasts until `EditFinished`
+    EditStarted,
+    /// A slider was released, ending the edit it was making
+    EditFinished,
+    BookmarksToggled,
+    BookmarkNameChanged(String),
+    BookmarkAdded,
+    BookmarkSelected(usize),
+    BookmarkRemoved(usize),
+    TimelineToggled,
+    PlaybackToggled,
+    /// The timeline was scrubbed to a time in seconds
+    TimeChanged(f32),
+    DurationChanged(f32),
+    ShutterAngleChanged(f32),
+    KeyParameterSelected(Parameter),
+    /// Keys the selected parameter at the current time
+    KeyAdded,
+    /// Keys everything about the camera's view at the current time
+    CameraKeyed,
+    KeyRemoved(usize),
+    KeyInterpolationSelected(usize, Interpolation),
+    /// A frame was drawn while flying to a bookmark or playing the timeline
+    Frame(Instant),
+    PostEffectAdded(Effect),
+    PostEffectToggled(usize, bool),
+    PostEffectMovedUp(usize),
+    PostEffectMovedDown(usize),
+    PostEffectRemoved(usize),
+    PostParameterChanged {
+        effect: usize,
+        parameter: usize,
+        value: f32,
+    },
+    FocusDistanceChanged(f32),
+    ParametersToggled,
+    CameraPositionEdited(vec_input::Message),
+    CameraDirectionEdited(vec_input::Message),
+    FovEdited(vec_input::Message),
+    DepthOfFieldEdited(vec_input::Message),
+    LightPositionEdited(vec_input::Message),
+    LightColorEdited(vec_input::Message),
+    MaxStepsEdited(vec_input::Message),
+    RelaxationEdited(vec_input::Message),
+    PixelEpsilonEdited(vec_input::Message),
+    SierpinskiIterationsEdited(vec_input::Message),
+    SierpinskiScaleEdited(vec_input::Message),
+    MandelboxIterationsEdited(vec_input::Message),
+    MandelboxScaleEdited(vec_input::Message),
+    MandelboxFoldingEdited(vec_input::Message),
+    CameraMoved {
+        position: Vec3,
+        direction: Vec3,
+    },
+}
+
+impl Message {
+    /// What the message is called in the undo history, if it edits the scene.
+    fn edit_label(&self) -> Option<&'static str> {
+        let label = match self {
+            Self::FractalSelected(_) => "Fractal",
+            Self::RenderModeSelected(_) => "View",
+            Self::StereoModeSelected(_) => "Stereo",
+            Self::ToneMapperSelected(_) => "Tone mapping",
+            Self::ExposureChanged(_) => "Exposure",
+            Self::LutLoaded => "Load LUT",
+            Self::LutStrengthChanged(_) => "LUT strength",
+            Self::SceneLoaded => "Load scene",
+            Self::PresetSelected(_) => "Preset",
+            Self::PostEffectAdded(_) => "Add effect",
+            Self::PostEffectToggled(..) => "Toggle effect",
+            Self::PostEffectMovedUp(_) | Self::PostEffectMovedDown(_) => "Reorder effects",
+            Self::PostEffectRemoved(_) => "Remove effect",
+            Self::PostParameterChanged { .. } => "Effect parameter",
+            Self::FocusDistanceChanged(_) => "Focus",
+            Self::CameraMoved { .. } => "Camera",
+            Self::BookmarkAdded => "Add bookmark",
+            Self::BookmarkRemoved(_) => "Remove bookmark",
+            Self::DurationChanged(_) => "Timeline length",
+            Self::ShutterAngleChanged(_) => "Shutter angle",
+            Self::KeyAdded | Self::CameraKeyed => "Add keyframe",
+            Self::KeyRemoved(_) => "Remove keyframe",
+            Self::KeyInterpolationSelected(..) => "Keyframe curve",
+            Self::LutPathChanged(_)
+            | Self::ScenePathChanged(_)
+            | Self::SceneSaved
+            | Self::PresetsToggled
+            | Self::ThumbnailsRendered(_)
+            | Self::Undo
+            | Self::Redo
+            | Self::HistoryToggled
+            | Self::HistorySelected(_)
+            | Self::EditStarted
+            | Self::EditFinished
+            | Self::BookmarksToggled
+            | Self::BookmarkNameChanged(_)
+            // Recorded once the flight lands instead
+            | Self::BookmarkSelected(_)
+            // Recorded by the `SceneLoaded` it turns into
+            | Self::FileDropped(_)
+            | Self::TimelineToggled
+            | Self::PlaybackToggled
+            | Self::TimeChanged(_)
+            | Self::KeyParameterSelected(_)
+            | Self::Frame(_)
+            | Self::ParametersToggled
+            // Recorded once the field settles on a valid value
+            | Self::CameraPositionEdited(_)
+            | Self::CameraDirectionEdited(_)
+            | Self::FovEdited(_)
+            | Self::DepthOfFieldEdited(_)
+            | Self::LightPositionEdited(_)
+            | Self::LightColorEdited(_)
+            | Self::MaxStepsEdited(_)
+            | Self::RelaxationEdited(_)
+            | Self::PixelEpsilonEdited(_)
+            | Self::SierpinskiIterationsEdited(_)
+            | Self::SierpinskiScaleEdited(_)
+            | Self::MandelboxIterationsEdited(_)
+            | Self::MandelboxScaleEdited(_)
+            | Self::MandelboxFoldingEdited(_) => return None,
+        };
+        Some(label)
+    }
+}
 
 pub struct App {
-    program: ShaderProgram,
+    scene: Scene,
+    history: History,
+    show_history: bool,
+    show_bookmarks: bool,
+    /// Contents of the new bookmark's name field
+    bookmark_name: String,
+    /// Flight to a bookmark in progress
+    transition: Option<Transition>,
+    show_timeline: bool,
+    /// Position of the playhead in seconds
+    time: f32,
+    /// When playback last advanced the playhead, while playing
+    playing: Option<Instant>,
+    /// Parameter whose keys the timeline shows and adds
+    key_parameter: Parameter,
+    /// Contents of the LUT path field, loaded on submit
+    lut_path: String,
+    /// Contents of the scene file path field
+    scene_path: String,
+    show_presets: bool,
+    show_parameters: bool,
+    parameter_inputs: ParameterInputs,
+    /// One for each of `PRESETS` once they've been rendered, `None` for any that failed
+    thumbnails: Option<Vec<Option<PathBuf>>>,
 }
 
-impl Sandbox for App {
+impl Application for App {
+    type Executor = executor::Default;
     type Message = Message;
+    type Theme = Theme;
+    type Flags = Scene;
 
-    fn new() -> Self {
-        Self {
-            program: ShaderProgram::new(),
-        }
+    fn new(scene: Self::Flags) -> (Self, Command<Self::Message>) {
+        let lut_path = lut_path(&scene);
+        let app = Self {
+            history: History::new(&scene),
+            show_history: false,
+            show_bookmarks: false,
+            bookmark_name: String::new(),
+            transition: None,
+            show_timeline: false,
+            time: 0.0,
+            playing: None,
+            key_parameter: Parameter::CameraX,
+            scene,
+            lut_path,
+            scene_path: String::from("scene.ron"),
+            show_presets: false,
+            show_parameters: false,
+            parameter_inputs: ParameterInputs::default(),
+            thumbnails: None,
+        };
+
+        (app, Command::none())
     }
 
     fn title(&self) -> String {
@@ -23,11 +238,952 @@ impl Sandbox for App {
     }
 
     fn view(&self) -> iced::Element<'_, Self::Message> {
-        iced::widget::shader(&self.program)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .into()
+        let file = row![
+            text("Scene"),
+            text_input("path to .ron or rendered .png", &self.scene_path)
+                .on_input(Message::ScenePathChanged)
+                .on_submit(Message::SceneLoaded)
+                .width(300),
+            button("Load").on_press(Message::SceneLoaded),
+            button("Save").on_press(Message::SceneSaved),
+            button("Presets").on_press(Message::PresetsToggled),
+            button("Parameters").on_press(Message::ParametersToggled),
+            button("Undo").on_press_maybe(self.history.can_undo().then_some(Message::Undo)),
+            button("Redo").on_press_maybe(self.history.can_redo().then_some(Message::Redo)),
+            button("History").on_press(Message::HistoryToggled),
+            button("Bookmarks").on_press(Message::BookmarksToggled),
+            button("Timeline").on_press(Message::TimelineToggled),
+        ]
+        .spacing(10)
+        .padding(5)
+        .align_items(Alignment::Center);
+
+        let toolbar = row![
+            text("Fractal"),
+            pick_list(
+                &Fractal::ALL[..],
+                Some(self.scene.fractal),
+                Message::FractalSelected
+            ),
+            text("View"),
+            pick_list(
+                &RenderMode::ALL[..],
+                Some(self.scene.render_mode),
+                Message::RenderModeSelected
+            ),
+            text("Stereo"),
+            pick_list(
+                &StereoMode::ALL[..],
+                Some(self.scene.stereo.mode),
+                Message::StereoModeSelected
+            ),
+            text("Tone mapping"),
+            pick_list(
+                &ToneMapper::ALL[..],
+                Some(self.scene.tone_mapping.mapper),
+                Message::ToneMapperSelected
+            ),
+            text("Exposure"),
+            slider(
+                -8.0..=8.0,
+                self.scene.tone_mapping.exposure,
+                Message::ExposureChanged
+            )
+            .on_release(Message::EditFinished)
+            .step(0.1)
+            .width(150),
+            text(format!("{:+.1}", self.scene.tone_mapping.exposure)),
+            text("LUT"),
+            text_input("path to .cube", &self.lut_path)
+                .on_input(Message::LutPathChanged)
+                .on_submit(Message::LutLoaded)
+                .width(200),
+            slider(
+                0.0..=1.0,
+                self.scene.look.strength,
+                Message::LutStrengthChanged
+            )
+            .on_release(Message::EditFinished)
+            .step(0.01)
+            .width(100),
+        ]
+        .spacing(10)
+        .padding(5)
+        .align_items(Alignment::Center);
+
+        column![
+            file,
+            toolbar,
+            Row::new()
+                .push_maybe(self.show_history.then(|| self.history_panel()))
+                .push_maybe(self.show_presets.then(|| self.preset_panel()))
+                .push_maybe(self.show_parameters.then(|| self.parameter_panel()))
+                .push(
+                    iced::widget::shader(ShaderProgram::new(&self.scene))
+                        .width(Length::Fill)
+                        .height(Length::Fill),
+                )
+                .push_maybe(self.show_bookmarks.then(|| self.bookmark_panel()))
+                .push(self.post_panel()),
+        ]
+        .push_maybe(self.show_timeline.then(|| self.timeline_panel()))
+        .into()
     }
 
-    fn update(&mut self, message: Self::Message) {}
+    fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
+        let edit = message.edit_label();
+
+        match message {
+            Message::FractalSelected(fractal) => self.scene.fractal = fractal,
+            Message::RenderModeSelected(mode) => self.scene.render_mode = mode,
+            Message::StereoModeSelected(mode) => self.scene.stereo.mode = mode,
+            Message::ToneMapperSelected(mapper) => self.scene.tone_mapping.mapper = mapper,
+            Message::ExposureChanged(exposure) => self.scene.tone_mapping.exposure = exposure,
+            Message::LutPathChanged(path) => self.lut_path = path,
+            Message::LutLoaded => {
+                if self.lut_path.is_empty() {
+                    self.scene.look.path = None;
+                    self.scene.look.lut = None;
+                } else if let Err(error) = self.scene.look.load(&self.lut_path) {
+                    eprintln!("Failed to load LUT {}: {error}", self.lut_path);
+                    return Command::none();
+                }
+            }
+            Message::LutStrengthChanged(strength) => self.scene.look.strength = strength,
+            Message::ScenePathChanged(path) => self.scene_path = path,
+            Message::SceneLoaded => match Scene::load(&self.scene_path) {
+                Ok(scene) => self.restore(scene),
+                Err(error) => {
+                    eprintln!("Failed to load scene {}: {error}", self.scene_path);
+                    return Command::none();
+                }
+            },
+            Message::FileDropped(path) => {
+                self.scene_path = path.display().to_string();
+                return self.update(Message::SceneLoaded);
+            }
+            Message::SceneSaved => {
+                if let Err(error) = self.scene.save(&self.scene_path) {
+                    eprintln!("Failed to save scene {}: {error}", self.scene_path)
+                }
+            }
+            Message::PresetsToggled => {
+                self.show_presets = !self.show_presets;
+
+                // Render thumbnails the first time the gallery opens, off the UI thread
+                if self.show_presets && self.thumbnails.is_none() {
+                    self.thumbnails = Some(vec![None; PRESETS.len()]);
+                    return Command::perform(
+                        async { presets::thumbnails() },
+                        Message::ThumbnailsRendered,
+                    );
+                }
+            }
+            Message::ThumbnailsRendered(thumbnails) => self.thumbnails = Some(thumbnails),
+            Message::PresetSelected(index) => match PRESETS[index].scene() {
+                Ok(scene) => self.restore(scene),
+                Err(error) => {
+                    eprintln!("Failed to load preset {}: {error}", PRESETS[index].name);
+                    return Command::none();
+                }
+            },
+            Message::Undo => {
+                if let Some(scene) = self.history.undo() {
+                    let scene = scene.clone();
+                    self.restore(scene);
+                }
+            }
+            Message::Redo => {
+                if let Some(scene) = self.history.redo() {
+                    let scene = scene.clone();
+                    self.restore(scene);
+                }
+            }
+            Message::HistoryToggled => self.show_history = !self.show_history,
+            Message::HistorySelected(index) => {
+                if let Some(scene) = self.history.jump(index) {
+                    let scene = scene.clone();
+                    self.restore(scene);
+                }
+            }
+            Message::EditStarted => self.history.begin(),
+            Message::EditFinished => self.history.seal(),
+            Message::BookmarksToggled => self.show_bookmarks = !self.show_bookmarks,
+            Message::BookmarkNameChanged(name) => self.bookmark_name = name,
+            Message::BookmarkAdded => {
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("View {}", self.scene.bookmarks.len() + 1),
+                    name => name.to_owned(),
+                };
+                self.scene
+                    .bookmarks
+                    .push(Bookmark::new(name, &self.scene.camera));
+                self.bookmark_name.clear();
+            }
+            Message::BookmarkSelected(index) => {
+                let fractal = self.scene.fractal;
+                let parameters = self.scene.fractal_parameters;
+                let min_distance = self.scene.marcher.min_distance;
+                self.transition = Some(Transition::new(
+                    &self.scene.camera,
+                    &self.scene.bookmarks[index],
+                    |point| {
+                        sdf::sdf(fractal, &parameters, point)
+                            .abs()
+                            .max(min_distance)
+                    },
+                ));
+            }
+            Message::BookmarkRemoved(index) => {
+                self.scene.bookmarks.remove(index);
+            }
+            Message::TimelineToggled => self.show_timeline = !self.show_timeline,
+            Message::PlaybackToggled => {
+                self.playing = match self.playing {
+                    Some(_) => None,
+                    None => {
+                        if self.time >= self.scene.timeline.duration {
+                            self.time = 0.0;
+                        }
+                        Some(Instant::now())
+                    }
+                };
+            }
+            Message::TimeChanged(time) => {
+                self.time = time;
+                self.scene.seek(time);
+            }
+            Message::DurationChanged(duration) => {
+                self.scene.timeline.duration = duration;
+                self.time = self.time.min(duration);
+            }
+            Message::ShutterAngleChanged(angle) => self.scene.timeline.shutter_angle = angle,
+            Message::KeyParameterSelected(parameter) => self.key_parameter = parameter,
+            Message::KeyAdded => {
+                let value = self.key_parameter.get(&self.scene);
+                self.scene
+                    .timeline
+                    .set_key(self.key_parameter, self.time, value);
+            }
+            Message::CameraKeyed => {
+                for parameter in Parameter::CAMERA {
+                    let value = parameter.get(&self.scene);
+                    self.scene.timeline.set_key(parameter, self.time, value);
+                }
+            }
+            Message::KeyRemoved(index) => {
+                self.scene.timeline.remove_key(self.key_parameter, index);
+                self.scene.seek(self.time);
+            }
+            Message::KeyInterpolationSelected(index, interpolation) => {
+                self.scene
+                    .timeline
+                    .set_interpolation(self.key_parameter, index, interpolation);
+                self.scene.seek(self.time);
+            }
+            Message::Frame(now) => {
+                if let Some(transition) = &self.transition {
+                    let (view, landed) = transition.at(now);
+                    view.apply(&mut self.scene.camera);
+                    if landed {
+                        self.transition = None;
+                        self.history.record("Fly to bookmark", &self.scene);
+                    }
+                }
+
+                if let Some(last) = self.playing {
+                    // Loop back to the start at the end
+                    self.time = (self.time + (now - last).as_secs_f32())
+                        % self.scene.timeline.duration.max(f32::EPSILON);
+                    self.playing = Some(now);
+                    self.scene.seek(self.time);
+                }
+            }
+            Message::PostEffectAdded(effect) => self.scene.post.push(PostEffect::new(effect)),
+            Message::PostEffectToggled(index, enabled) => self.scene.post[index].enabled = enabled,
+            Message::PostEffectMovedUp(index) => self.scene.post.swap(index - 1, index),
+            Message::PostEffectMovedDown(index) => self.scene.post.swap(index, index + 1),
+            Message::PostEffectRemoved(index) => {
+                self.scene.post.remove(index);
+            }
+            Message::PostParameterChanged {
+                effect,
+                parameter,
+                value,
+            } => self.scene.post[effect]
+                .effect
+                .set_parameter(parameter, value),
+            Message::FocusDistanceChanged(distance) => self.scene.camera.focus_distance = distance,
+            Message::ParametersToggled => self.show_parameters = !self.show_parameters,
+            Message::CameraPositionEdited(message) => {
+                let camera = &self.scene.camera;
+                let action = self
+                    .parameter_inputs
+                    .camera_position
+                    .update(message, camera.position.to_array());
+                self.edit_parameter("Camera", action, |scene, position| {
+                    scene.camera.position = Vec3::from_array(position)
+                });
+            }
+            Message::CameraDirectionEdited(message) => {
+                let camera = &self.scene.camera;
+                let action = self
+                    .parameter_inputs
+                    .camera_direction
+                    .update(message, camera.direction.to_array());
+                self.edit_parameter("Camera", action, |scene, direction| {
+                    if let Some(direction) = camera::usable_direction(Vec3::from_array(direction)) {
+                        scene.camera.direction = direction;
+                    }
+                });
+            }
+            Message::FovEdited(message) => {
+                let camera = &self.scene.camera;
+                let action = self.parameter_inputs.fov.update(message, [camera.fov]);
+                self.edit_parameter("Field of view", action, |scene, [fov]| {
+                    scene.camera.fov = fov
+                });
+            }
+            Message::DepthOfFieldEdited(message) => {
+                let camera = &self.scene.camera;
+                let action = self
+                    .parameter_inputs
+                    .depth_of_field
+                    .update(message, [camera.aperture, camera.focus_distance]);
+                self.edit_parameter("Depth of field", action, |scene, [aperture, focus]| {
+                    scene.camera.aperture = aperture;
+                    scene.camera.focus_distance = focus;
+                });
+            }
+            Message::LightPositionEdited(message) => {
+                let light = &self.scene.light;
+                let action = self
+                    .parameter_inputs
+                    .light_position
+                    .update(message, light.position.to_array());
+                self.edit_parameter("Light", action, |scene, position| {
+                    scene.light.position = Vec3::from_array(position)
+                });
+            }
+            Message::LightColorEdited(message) => {
+                let light = &self.scene.light;
+                let action = self
+                    .parameter_inputs
+                    .light_color
+                    .update(message, light.color.to_array());
+                self.edit_parameter("Light color", action, |scene, color| {
+                    scene.light.color = Vec3::from_array(color)
+                });
+            }
+            Message::MaxStepsEdited(message) => {
+                let marcher = &self.scene.marcher;
+                let action = self
+                    .parameter_inputs
+                    .max_steps
+                    .update(message, [marcher.max_steps as f32]);
+                self.edit_parameter("Max steps", action, |scene, [steps]| {
+                    scene.marcher.max_steps = steps.round() as u32
+                });
+            }
+            Message::RelaxationEdited(message) => {
+                let marcher = &self.scene.marcher;
+                let action = self
+                    .parameter_inputs
+                    .relaxation
+                    .update(message, [marcher.relaxation]);
+                self.edit_parameter("Relaxation", action, |scene, [relaxation]| {
+                    scene.marcher.relaxation = relaxation
+                });
+            }
+            Message::PixelEpsilonEdited(message) => {
+                let marcher = &self.scene.marcher;
+                let action = self
+                    .parameter_inputs
+                    .pixel_epsilon
+                    .update(message, [marcher.pixel_epsilon]);
+                self.edit_parameter("Detail", action, |scene, [epsilon]| {
+                    scene.marcher.pixel_epsilon = epsilon
+                });
+            }
+            Message::SierpinskiIterationsEdited(message) => {
+                let sierpinski = &self.scene.fractal_parameters.sierpinski;
+                let action = self
+                    .parameter_inputs
+                    .sierpinski_iterations
+                    .update(message, [sierpinski.iterations as f32]);
+                self.edit_parameter("Iterations", action, |scene, [iterations]| {
+                    scene.fractal_parameters.sierpinski.iterations = iterations.round() as u32
+                });
+            }
+            Message::SierpinskiScaleEdited(message) => {
+                let sierpinski = &self.scene.fractal_parameters.sierpinski;
+                let action = self
+                    .parameter_inputs
+                    .sierpinski_scale
+                    .update(message, [sierpinski.scale]);
+                self.edit_parameter("Fractal scale", action, |scene, [scale]| {
+                    scene.fractal_parameters.sierpinski.scale = scale
+                });
+            }
+            Message::MandelboxIterationsEdited(message) => {
+                let mandelbox = &self.scene.fractal_parameters.mandelbox;
+                let action = self
+                    .parameter_inputs
+                    .mandelbox_iterations
+                    .update(message, [mandelbox.iterations as f32]);
+                self.edit_parameter("Iterations", action, |scene, [iterations]| {
+                    scene.fractal_parameters.mandelbox.iterations = iterations.round() as u32
+                });
+            }
+            Message::MandelboxScaleEdited(message) => {
+                let mandelbox = &self.scene.fractal_parameters.mandelbox;
+                let action = self
+                    .parameter_inputs
+                    .mandelbox_scale
+                    .update(message, [mandelbox.scale]);
+                self.edit_parameter("Fractal scale", action, |scene, [scale]| {
+                    scene.fractal_parameters.mandelbox.scale = scale
+                });
+            }
+            Message::MandelboxFoldingEdited(message) => {
+                let mandelbox = &self.scene.fractal_parameters.mandelbox;
+                let action = self.parameter_inputs.mandelbox_folding.update(
+                    message,
+                    [
+                        mandelbox.fold_limit,
+                        mandelbox.min_radius,
+                        mandelbox.max_radius,
+                    ],
+                );
+                self.edit_parameter("Folding", action, |scene, [limit, min, max]| {
+                    let mandelbox = &mut scene.fractal_parameters.mandelbox;
+                    mandelbox.fold_limit = limit;
+                    mandelbox.min_radius = min;
+                    mandelbox.max_radius = max;
+                });
+            }
+            Message::CameraMoved {
+                position,
+                direction,
+            } => {
+                // Taking over the controls cancels any flight to a bookmark
+                self.transition = None;
+                self.scene.camera.position = position;
+                self.scene.camera.direction = direction;
+            }
+        }
+
+        if let Some(label) = edit {
+            self.history.record(label, &self.scene);
+        }
+
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let shortcuts = keyboard::on_key_press(|key, modifiers| match key.as_ref() {
+            keyboard::Key::Character(c) if modifiers.command() && c.eq_ignore_ascii_case("z") => {
+                Some(if modifiers.shift() {
+                    Message::Redo
+                } else {
+                    Message::Undo
+                })
+            }
+            _ => None,
+        });
+
+        let frames = if self.transition.is_some() || self.playing.is_some() {
+            window::frames().map(Message::Frame)
+        } else {
+            Subscription::none()
+        };
+
+        let dropped_files = event::listen_with(|event, _| match event {
+            Event::Window(_, window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
+            _ => None,
+        });
+
+        Subscription::batch([shortcuts, frames, dropped_files])
+    }
+}
+
+impl App {
+    /// Switches to another scene wholesale, from a file or the history.
+    fn restore(&mut self, scene: Scene) {
+        self.transition = None;
+        self.lut_path = lut_path(&scene);
+        self.scene = scene;
+    }
+
+    /// Applies the value a parameter field settled on and records it in the history, or closes
+    /// the edit once the field is done with.
+    fn edit_parameter<const N: usize>(
+        &mut self,
+        label: &'static str,
+        action: vec_input::Action<N>,
+        apply: impl FnOnce(&mut Scene, [f32; N]),
+    ) {
+        match action {
+            vec_input::Action::Change(value) => {
+                // Typing over the view cancels any flight to a bookmark, like the controls do
+                self.transition = None;
+                apply(&mut self.scene, value);
+                self.history.record(label, &self.scene);
+            }
+            vec_input::Action::Finish => self.history.seal(),
+            vec_input::Action::None => {}
+        }
+    }
+
+    /// Camera, light and fractal settings as number fields. Dragging a field's label scrubs it.
+    fn parameter_panel(&self) -> Element<'_, Message> {
+        let inputs = &self.parameter_inputs;
+        let camera = &self.scene.camera;
+        let light = &self.scene.light;
+        let marcher = &self.scene.marcher;
+
+        let camera = column![
+            text("Camera").size(18),
+            inputs
+                .camera_position
+                .view(camera.position.to_array())
+                .map(Message::CameraPositionEdited),
+            inputs
+                .camera_direction
+                .view(camera.direction.to_array())
+                .map(Message::CameraDirectionEdited),
+            inputs.fov.view([camera.fov]).map(Message::FovEdited),
+            inputs
+                .depth_of_field
+                .view([camera.aperture, camera.focus_distance])
+                .map(Message::DepthOfFieldEdited),
+        ]
+        .spacing(8);
+
+        let light = column![
+            text("Light").size(18),
+            inputs
+                .light_position
+                .view(light.position.to_array())
+                .map(Message::LightPositionEdited),
+            inputs
+                .light_color
+                .view(light.color.to_array())
+                .map(Message::LightColorEdited),
+        ]
+        .spacing(8);
+
+        let fractal_parameters = &self.scene.fractal_parameters;
+        let fractal_inputs = match self.scene.fractal {
+            Fractal::SphereLattice => None,
+            Fractal::Sierpinski => {
+                let sierpinski = &fractal_parameters.sierpinski;
+                Some(column![
+                    inputs
+                        .sierpinski_iterations
+                        .view([sierpinski.iterations as f32])
+                        .map(Message::SierpinskiIterationsEdited),
+                    inputs
+                        .sierpinski_scale
+                        .view([sierpinski.scale])
+                        .map(Message::SierpinskiScaleEdited),
+                ])
+            }
+            Fractal::Mandelbox => {
+                let mandelbox = &fractal_parameters.mandelbox;
+                Some(column![
+                    inputs
+                        .mandelbox_iterations
+                        .view([mandelbox.iterations as f32])
+                        .map(Message::MandelboxIterationsEdited),
+                    inputs
+                        .mandelbox_scale
+                        .view([mandelbox.scale])
+                        .map(Message::MandelboxScaleEdited),
+                    inputs
+                        .mandelbox_folding
+                        .view([
+                            mandelbox.fold_limit,
+                            mandelbox.min_radius,
+                            mandelbox.max_radius
+                        ])
+                        .map(Message::MandelboxFoldingEdited),
+                ])
+            }
+        };
+
+        let fractal = column![
+            text("Fractal").size(18),
+            pick_list(
+                &Fractal::ALL[..],
+                Some(self.scene.fractal),
+                Message::FractalSelected
+            )
+            .width(Length::Fill),
+        ]
+        .push_maybe(fractal_inputs.map(|inputs| inputs.spacing(8)))
+        .push(
+            column![
+                inputs
+                    .max_steps
+                    .view([marcher.max_steps as f32])
+                    .map(Message::MaxStepsEdited),
+                inputs
+                    .relaxation
+                    .view([marcher.relaxation])
+                    .map(Message::RelaxationEdited),
+                inputs
+                    .pixel_epsilon
+                    .view([marcher.pixel_epsilon])
+                    .map(Message::PixelEpsilonEdited),
+            ]
+            .spacing(8),
+        )
+        .spacing(8);
+
+        column![
+            text("Parameters"),
+            scrollable(
+                column![camera, light, fractal]
+                    .spacing(20)
+                    .padding([0, 10, 0, 0])
+            )
+            .height(Length::Fill),
+        ]
+        .spacing(10)
+        .padding(5)
+        .width(280)
+        .into()
+    }
+
+    /// Every step in the undo history, oldest first. Clicking one goes back, or forward, to it.
+    fn history_panel(&self) -> Element<'_, Message> {
+        let current = self.history.current();
+        let entries = self
+            .history
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let style = if index == current {
+                    theme::Button::Primary
+                } else {
+                    theme::Button::Text
+                };
+
+                button(text(entry.label))
+                    .style(style)
+                    .on_press(Message::HistorySelected(index))
+                    .width(Length::Fill)
+                    .into()
+            });
+
+        column![
+            text("History"),
+            scrollable(Column::with_children(entries).spacing(2)).height(Length::Fill),
+        ]
+        .spacing(10)
+        .padding(5)
+        .width(160)
+        .into()
+    }
+
+    /// Bundled scenes to start from, with a thumbnail of each.
+    fn preset_panel(&self) -> Element<'_, Message> {
+        let presets = PRESETS.iter().enumerate().map(|(index, preset)| {
+            let thumbnail: Element<'_, Message> = match self
+                .thumbnails
+                .as_ref()
+                .and_then(|thumbnails| thumbnails[index].clone())
+            {
+                Some(path) => image(path).width(Length::Fill).into(),
+                None => container(text("Rendering…"))
+                    .width(Length::Fill)
+                    .height(90)
+                    .center_x()
+                    .center_y()
+                    .into(),
+            };
+
+            button(column![thumbnail, text(preset.name)].spacing(5))
+                .on_press(Message::PresetSelected(index))
+                .width(Length::Fill)
+                .into()
+        });
+
+        column![
+            text("Presets"),
+            scrollable(Column::with_children(presets).spacing(10)).height(Length::Fill),
+        ]
+        .spacing(10)
+        .padding(5)
+        .width(200)
+        .into()
+    }
+
+    /// Saved views, with a field to name the current one. Clicking a bookmark flies to it.
+    fn bookmark_panel(&self) -> Element<'_, Message> {
+        let bookmarks = self
+            .scene
+            .bookmarks
+            .iter()
+            .enumerate()
+            .map(|(index, bookmark)| {
+                row![
+                    button(text(&bookmark.name))
+                        .style(theme::Button::Text)
+                        .on_press(Message::BookmarkSelected(index))
+                        .width(Length::Fill),
+                    button("✕").on_press(Message::BookmarkRemoved(index)),
+                ]
+                .spacing(5)
+                .align_items(Alignment::Center)
+                .into()
+            });
+
+        let add = row![
+            text_input("name", &self.bookmark_name)
+                .on_input(Message::BookmarkNameChanged)
+                .on_submit(Message::BookmarkAdded),
+            button("Add").on_press(Message::BookmarkAdded),
+        ]
+        .spacing(5);
+
+        column![
+            text("Bookmarks"),
+            scrollable(Column::with_children(bookmarks).spacing(2)).height(Length::Fill),
+            add,
+        ]
+        .spacing(10)
+        .padding(5)
+        .width(200)
+        .into()
+    }
+
+    /// Playback controls and the keys of one parameter, with a slider to scrub through time.
+    fn timeline_panel(&self) -> Element<'_, Message> {
+        let timeline = &self.scene.timeline;
+
+        let transport = row![
+            button(if self.playing.is_some() {
+                "Pause"
+            } else {
+                "Play"
+            })
+            .on_press(Message::PlaybackToggled),
+            slider(0.0..=timeline.duration, self.time, Message::TimeChanged).step(0.01),
+            text(format!("{:.2} / {:.2} s", self.time, timeline.duration)).width(110),
+            text("Length"),
+            slider(1.0..=120.0, timeline.duration, Message::DurationChanged)
+                .on_release(Message::EditFinished)
+                .step(0.5)
+                .width(150),
+            text("Shutter"),
+            slider(
+                0.0..=360.0,
+                timeline.shutter_angle,
+                Message::ShutterAngleChanged
+            )
+            .on_release(Message::EditFinished)
+            .step(15.0)
+            .width(120),
+            text(format!("{:.0}°", timeline.shutter_angle)).width(40),
+        ]
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        let keys = timeline
+            .track(self.key_parameter)
+            .map(|track| &track.keyframes[..])
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                row![
+                    button(text(format!("{:.2} s: {:.3}", key.time, key.value)))
+                        .style(theme::Button::Text)
+                        .on_press(Message::TimeChanged(key.time)),
+                    pick_list(
+                        &Interpolation::ALL[..],
+                        Some(key.interpolation),
+                        move |interpolation| {
+                            Message::KeyInterpolationSelected(index, interpolation)
+                        }
+                    ),
+                    button("✕").on_press(Message::KeyRemoved(index)),
+                ]
+                .spacing(5)
+                .align_items(Alignment::Center)
+                .into()
+            });
+
+        let keying = row![
+            pick_list(
+                &Parameter::ALL[..],
+                Some(self.key_parameter),
+                Message::KeyParameterSelected
+            ),
+            button("Key").on_press(Message::KeyAdded),
+            button("Key camera").on_press(Message::CameraKeyed),
+            scrollable(Row::with_children(keys).spacing(15)).direction(
+                scrollable::Direction::Horizontal(scrollable::Properties::default())
+            ),
+        ]
+        .spacing(10)
+        .align_items(Alignment::Center);
+
+        column![transport, keying].spacing(10).padding(5).into()
+    }
+
+    /// The post processing stack, top to bottom in the order it's applied.
+    fn post_panel(&self) -> Element<'_, Message> {
+        let last = self.scene.post.len().saturating_sub(1);
+        let effects = self.scene.post.iter().enumerate().map(|(index, post)| {
+            let header = row![
+                checkbox(post.effect.to_string(), post.enabled)
+                    .on_toggle(move |enabled| Message::PostEffectToggled(index, enabled))
+                    .width(Length::Fill),
+                button("↑")
+                    .on_press_maybe((index > 0).then_some(Message::PostEffectMovedUp(index))),
+                button("↓")
+                    .on_press_maybe((index < last).then_some(Message::PostEffectMovedDown(index))),
+                button("✕").on_press(Message::PostEffectRemoved(index)),
+            ]
+            .spacing(5)
+            .align_items(Alignment::Center);
+
+            let parameters =
+                post.effect
+                    .parameters()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(parameter, info)| {
+                        row![
+                            text(info.name).width(80),
+                            slider(info.range, info.value, move |value| {
+                                Message::PostParameterChanged {
+                                    effect: index,
+                                    parameter,
+                                    value,
+                                }
+                            })
+                            .on_release(Message::EditFinished)
+                            .step(0.01),
+                            text(format!("{:.2}", info.value)).width(40),
+                        ]
+                        .spacing(5)
+                        .align_items(Alignment::Center)
+                        .into()
+                    });
+
+            column![header, Column::with_children(parameters).spacing(2)]
+                .spacing(5)
+                .into()
+        });
+
+        let add = pick_list(&Effect::ALL[..], None::<Effect>, Message::PostEffectAdded)
+            .placeholder("Add effect");
+
+        column![
+            text("Post processing"),
+            scrollable(Column::with_children(effects).spacing(15)).height(Length::Fill),
+            add,
+        ]
+        .spacing(10)
+        .padding(5)
+        .width(300)
+        .into()
+    }
+}
+
+/// The scene's LUT path, for the LUT field.
+fn lut_path(scene: &Scene) -> String {
+    scene
+        .look
+        .path
+        .as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+/// Fields of the parameter panel, which hold on to text typed into them between updates.
+struct ParameterInputs {
+    camera_position: VecInput<3>,
+    camera_direction: VecInput<3>,
+    fov: VecInput<1>,
+    depth_of_field: VecInput<2>,
+    light_position: VecInput<3>,
+    light_color: VecInput<3>,
+    max_steps: VecInput<1>,
+    relaxation: VecInput<1>,
+    pixel_epsilon: VecInput<1>,
+    sierpinski_iterations: VecInput<1>,
+    sierpinski_scale: VecInput<1>,
+    mandelbox_iterations: VecInput<1>,
+    mandelbox_scale: VecInput<1>,
+    mandelbox_folding: VecInput<3>,
+}
+
+impl Default for ParameterInputs {
+    fn default() -> Self {
+        Self {
+            camera_position: VecInput::new().title("Position").step(0.01),
+            camera_direction: VecInput::new()
+                .title("Direction")
+                .range(-1.0..=1.0)
+                .step(0.005),
+            fov: VecInput::new()
+                .labels(["Field of view"])
+                .range(1.0..=179.0)
+                .step(0.5)
+                .unit("°"),
+            depth_of_field: VecInput::new()
+                .title("Depth of field")
+                .labels(["Aperture", "Focus"])
+                .range(0.0..=1000.0)
+                .step(0.001),
+            light_position: VecInput::new().title("Position").step(0.05),
+            light_color: VecInput::new()
+                .title("Color")
+                .labels(["R", "G", "B"])
+                .range(0.0..=100.0)
+                .step(0.01),
+            max_steps: VecInput::new()
+                .labels(["Max steps"])
+                .range(1.0..=10000.0)
+                .step(1.0),
+            relaxation: VecInput::new()
+                .labels(["Relaxation"])
+                .range(RELAXATION_RANGE)
+                .step(0.001),
+            pixel_epsilon: VecInput::new()
+                .labels(["Detail"])
+                .range(0.01..=10.0)
+                .step(0.01)
+                .unit("px"),
+            sierpinski_iterations: VecInput::new()
+                .labels(["Iterations"])
+                .range(1.0..=30.0)
+                .step(1.0),
+            // Folds below a scale of 1 contract to nothing
+            sierpinski_scale: VecInput::new()
+                .labels(["Scale"])
+                .range(1.01..=4.0)
+                .step(0.005),
+            mandelbox_iterations: VecInput::new()
+                .labels(["Iterations"])
+                .range(1.0..=100.0)
+                .step(1.0),
+            mandelbox_scale: VecInput::new()
+                .labels(["Scale"])
+                .range(-4.0..=4.0)
+                .step(0.005),
+            // A min radius of 0 would divide by zero at the origin
+            mandelbox_folding: VecInput::new()
+                .title("Folding")
+                .labels(["Limit", "Min", "Max"])
+                .range(0.01..=4.0)
+                .step(0.005),
+        }
+    }
 }
@@ -0,0 +1,33 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Participating media between the camera and the fractal. Everything is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Atmosphere {
+    pub fog_color: Vec3,
+    /// Extinction per unit distance at `fog_height`
+    pub fog_density: f32,
+    pub fog_height: f32,
+    /// How quickly fog thins out above `fog_height`. Zero gives uniform distance fog
+    pub fog_height_falloff: f32,
+    pub glow_color: Vec3,
+    /// Brightness of the glow around near misses, scaled by the fraction of steps spent
+    pub glow_intensity: f32,
+    /// Density of the medium scattering the point light towards the camera
+    pub scattering: f32,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            fog_color: Vec3::new(0.5, 0.6, 0.7),
+            fog_density: 0.0,
+            fog_height: 0.0,
+            fog_height_falloff: 0.0,
+            glow_color: Vec3::new(1.0, 0.8, 0.5),
+            glow_intensity: 0.0,
+            scattering: 0.0,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// What missed rays return.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    Solid(Vec3),
+    /// Blends from `bottom` straight down to `top` straight up, by world space elevation
+    Gradient {
+        top: Vec3,
+        bottom: Vec3,
+    },
+    /// Analytic daylight sky with a sun disk
+    Sky {
+        sun_direction: Vec3,
+        sun_intensity: f32,
+    },
+    /// Samples the scene's environment map, falling back to black without one
+    Environment,
+    /// Zero alpha, for compositing
+    Transparent,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Solid(Vec3::new(0.5, 0.0, 0.0))
+    }
+}
+
+impl Background {
+    pub fn gradient() -> Self {
+        Self::Gradient {
+            top: Vec3::new(0.35, 0.45, 0.6),
+            bottom: Vec3::new(0.05, 0.05, 0.07),
+        }
+    }
+
+    pub fn sky() -> Self {
+        Self::Sky {
+            sun_direction: Vec3::new(-0.4, 0.3, 0.5).normalize(),
+            sun_intensity: 20.0,
+        }
+    }
+
+    /// Index of the matching branch in `shader.wgsl`'s `background`.
+    pub fn mode(&self) -> u32 {
+        match self {
+            Self::Solid(_) => 0,
+            Self::Gradient { .. } => 1,
+            Self::Sky { .. } => 2,
+            Self::Environment => 3,
+            Self::Transparent => 4,
+        }
+    }
+}
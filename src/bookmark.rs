@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use glam::{Mat3, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{self, Camera};
+
+/// How long flying to a bookmark takes.
+const TRANSITION_DURATION: Duration = Duration::from_millis(1500);
+
+/// A named camera view, saved with the scene.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Field of view in degrees, along the camera's `fov_axis`
+    pub fov: f32,
+    pub focus_distance: f32,
+}
+
+impl Bookmark {
+    pub fn new(name: String, camera: &Camera) -> Self {
+        Self {
+            name,
+            position: camera.position,
+            direction: camera.direction,
+            fov: camera.fov,
+            focus_distance: camera.focus_distance,
+        }
+    }
+
+    /// Moves the camera to the bookmarked view, leaving the rest of its settings alone.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position;
+        camera.direction = self.direction;
+        camera.fov = self.fov;
+        camera.focus_distance = self.focus_distance;
+    }
+}
+
+/// An eased flight from one view to another.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    from: Bookmark,
+    to: Bookmark,
+    /// Distance to the surface at each end, which sets how fast the camera moves through that
+    /// part of the fractal
+    from_scale: f32,
+    to_scale: f32,
+    start: Instant,
+}
+
+impl Transition {
+    /// Starts flying from the camera's current view. `scale` gives the distance to the fractal's
+    /// surface at a point.
+    pub fn new(camera: &Camera, to: &Bookmark, scale: impl Fn(Vec3) -> f32) -> Self {
+        Self {
+            from: Bookmark::new(String::new(), camera),
+            to: to.clone(),
+            from_scale: scale(camera.position),
+            to_scale: scale(to.position),
+            start: Instant::now(),
+        }
+    }
+
+    /// The view at `now`, and whether the flight is over.
+    pub fn at(&self, now: Instant) -> (Bookmark, bool) {
+        let t = (now - self.start).as_secs_f32() / TRANSITION_DURATION.as_secs_f32();
+        if t >= 1.0 {
+            return (self.to.clone(), true);
+        }
+
+        // Smootherstep, so the flight eases in and out without a jolt
+        let eased = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+
+        let from = &self.from;
+        let to = &self.to;
+
+        // Turning the whole camera, rather than just its direction, gives a single way round
+        // even between opposite directions
+        let orientation = orientation(from.direction).slerp(orientation(to.direction), eased);
+        let direction = orientation * Vec3::NEG_Z;
+        // Tipping over the top on the way is fine, but not stopping right on it, where the
+        // direction alone can't tell which way is up
+        let direction = camera::usable_direction(direction)
+            .unwrap_or_else(|| (direction + orientation * Vec3::Y * 1e-3).normalize());
+
+        let view = Bookmark {
+            name: to.name.clone(),
+            position: from.position.lerp(to.position, self.travelled(eased)),
+            direction,
+            fov: from.fov + (to.fov - from.fov) * eased,
+            focus_distance: log_lerp(from.focus_distance, to.focus_distance, eased),
+        };
+
+        (view, false)
+    }
+
+    /// Fraction of the way along the path at `t`. The scale is interpolated logarithmically and
+    /// the position follows it, so zooming into fine detail covers the coarse part of the trip
+    /// quickly and slows down as the detail gets smaller.
+    fn travelled(&self, t: f32) -> f32 {
+        let (from, to) = (self.from_scale, self.to_scale);
+        if (to / from - 1.0).abs() < 1e-3 {
+            return t;
+        }
+
+        (log_lerp(from, to, t) - from) / (to - from)
+    }
+}
+
+/// Rotation taking -Z to `direction` and Y to its up, following `Camera::basis`.
+fn orientation(direction: Vec3) -> Quat {
+    let forward = direction.normalize();
+    let right = forward.cross(Vec3::Z).try_normalize().unwrap_or(Vec3::X);
+    let up = right.cross(forward);
+    Quat::from_mat3(&Mat3::from_cols(right, up, -forward))
+}
+
+/// Geometric interpolation between two positive values.
+fn log_lerp(from: f32, to: f32, t: f32) -> f32 {
+    from.max(f32::MIN_POSITIVE).powf(1.0 - t) * to.max(f32::MIN_POSITIVE).powf(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction_between(from: Vec3, to: Vec3, t: f32) -> Vec3 {
+        let camera = Camera {
+            direction: from,
+            ..Camera::default()
+        };
+        let to = Bookmark {
+            direction: to,
+            ..Bookmark::new(String::new(), &camera)
+        };
+        let transition = Transition::new(&camera, &to, |_| 1.0);
+        let (view, _) = transition.at(transition.start + TRANSITION_DURATION.mul_f32(t));
+        view.direction
+    }
+
+    #[test]
+    fn keeps_the_orientation_of_each_end() {
+        let from = Vec3::new(1.0, 0.0, 0.3).normalize();
+        let to = Vec3::new(0.0, -1.0, -0.5).normalize();
+        assert!(direction_between(from, to, 0.0).abs_diff_eq(from, 1e-5));
+        assert!(direction_between(from, to, 1.0).abs_diff_eq(to, 1e-5));
+    }
+
+    #[test]
+    fn turns_level_between_opposite_directions() {
+        for t in [0.25, 0.5, 0.75] {
+            let direction = direction_between(Vec3::X, Vec3::NEG_X, t);
+            assert!(direction.z.abs() < 1e-5, "{direction} at {t}");
+            assert!(camera::usable_direction(direction).is_some());
+        }
+    }
+
+    #[test]
+    fn never_stops_facing_straight_up() {
+        let from = Vec3::new(1.0, 0.0, 4.0).normalize();
+        let to = Vec3::new(-1.0, 0.0, 4.0).normalize();
+        for step in 0..=100 {
+            let direction = direction_between(from, to, step as f32 / 100.0);
+            assert!(camera::usable_direction(direction).is_some(), "{direction}");
+        }
+    }
+}
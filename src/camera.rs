@@ -1,6 +1,254 @@
-use glam::Vec3;
+use std::f32::consts::{PI, TAU};
 
-struct Camera {
-    position: Vec3,
-    direction: Vec3,
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel rays, with the view `orthographic_size` units tall
+    Orthographic,
+    /// Equidistant fisheye. Angle from the centre of the view is proportional to distance on
+    /// screen, up to 360° across
+    Fisheye,
+    /// Keeps verticals and lines through the centre straight, for very wide angles
+    Panini,
+    /// Full sphere panorama, 360° across and 180° up, ignoring the field of view
+    Equirectangular,
+    /// Full sphere panorama as six 90° faces in a 3x2 grid. Front, right and back along the top
+    /// row, then left, up and down
+    CubeMap,
+}
+
+impl Projection {
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Perspective => 0,
+            Self::Orthographic => 1,
+            Self::Fisheye => 2,
+            Self::Panini => 3,
+            Self::Equirectangular => 4,
+            Self::CubeMap => 5,
+        }
+    }
+
+    /// Whether the projection has a focal plane, rather than focusing on a sphere.
+    pub fn is_planar(&self) -> bool {
+        matches!(self, Self::Perspective | Self::Orthographic)
+    }
+
+    /// Width over height of an image that fits the projection exactly, if it needs one.
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        match self {
+            Self::Equirectangular => Some(2.0),
+            Self::CubeMap => Some(1.5),
+            _ => None,
+        }
+    }
+}
+
+/// Which edges of the view `Camera::fov` spans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FovAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Field of view in degrees
+    pub fov: f32,
+    pub fov_axis: FovAxis,
+    pub projection: Projection,
+    pub orthographic_size: f32,
+    /// Panini projection's distance parameter. 0 is plain perspective, 1 is the classic Panini
+    pub panini_distance: f32,
+    /// Radius of the thin lens. Zero gives a pinhole camera with everything in focus
+    pub aperture: f32,
+    /// Distance to the plane in focus, along the view direction
+    pub focus_distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(-4.0, 1.0, 1.0),
+            direction: Vec3::new(1.0, 0.0, -0.3),
+            fov: 50.0,
+            fov_axis: FovAxis::default(),
+            projection: Projection::default(),
+            orthographic_size: 4.0,
+            panini_distance: 1.0,
+            aperture: 0.0,
+            focus_distance: 4.0,
+        }
+    }
+}
+
+/// How big a pixel is at some distance from the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelSize {
+    /// Growth per unit distance
+    pub footprint: f32,
+    /// Constant part, for orthographic projection
+    pub width: f32,
+}
+
+impl PixelSize {
+    pub fn at(&self, distance: f32) -> f32 {
+        self.footprint * distance + self.width
+    }
+}
+
+/// `direction` normalized, or `None` if it's too short to have one or too near vertical for
+/// `Camera::basis` to tell right from left.
+pub fn usable_direction(direction: Vec3) -> Option<Vec3> {
+    direction
+        .try_normalize()
+        .filter(|direction| direction.cross(Vec3::Z).length_squared() > 1e-6)
+}
+
+impl Camera {
+    /// Forward, right and up vectors, matching `camera_basis` in `shader.wgsl`.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = self.direction.normalize();
+        let right = forward.cross(Vec3::Z).normalize();
+        let up = right.cross(forward);
+        (forward, right, up)
+    }
+
+    /// Camera heading with world up, for panoramas. Matches `level_basis` in `shader.wgsl`.
+    fn level_basis(&self) -> (Vec3, Vec3, Vec3) {
+        let heading = Vec3::new(self.direction.x, self.direction.y, 0.0);
+        let forward = heading.try_normalize().unwrap_or(Vec3::X);
+        let right = forward.cross(Vec3::Z);
+        (forward, right, Vec3::Z)
+    }
+
+    fn vertical_half_tangent(&self, aspect_ratio: f32) -> f32 {
+        let half_tangent = (self.fov.to_radians() * 0.5).tan();
+        match self.fov_axis {
+            FovAxis::Vertical => half_tangent,
+            FovAxis::Horizontal => half_tangent / aspect_ratio,
+        }
+    }
+
+    pub fn pixel_size(&self, height: f32, aspect_ratio: f32) -> PixelSize {
+        match self.projection {
+            Projection::Orthographic => PixelSize {
+                footprint: 0.0,
+                width: self.orthographic_size / height,
+            },
+            Projection::Equirectangular => PixelSize {
+                footprint: PI / height,
+                width: 0.0,
+            },
+            Projection::CubeMap => PixelSize {
+                footprint: 4.0 / height,
+                width: 0.0,
+            },
+            _ => PixelSize {
+                footprint: 2.0 * self.vertical_half_tangent(aspect_ratio) / height,
+                width: 0.0,
+            },
+        }
+    }
+
+    /// Origin and direction of the pinhole ray through `uv`, in [-0.5, 0.5] with the origin at
+    /// the centre of the image and y pointing up. Mirrors `camera_ray` in `shader.wgsl`.
+    ///
+    /// Returns `None` for points outside the projection.
+    pub fn ray(&self, uv: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3)> {
+        let (forward, right, up) = self.basis();
+        let screen = Vec2::new(uv.x * aspect_ratio, uv.y);
+        let plane = screen * 2.0 * self.vertical_half_tangent(aspect_ratio);
+
+        match self.projection {
+            Projection::Perspective => Some((
+                self.position,
+                (forward + plane.x * right + plane.y * up).normalize(),
+            )),
+            Projection::Orthographic => {
+                let offset = screen * self.orthographic_size;
+                Some((self.position + offset.x * right + offset.y * up, forward))
+            }
+            Projection::Fisheye => {
+                let vertical_fov = match self.fov_axis {
+                    FovAxis::Vertical => self.fov.to_radians(),
+                    FovAxis::Horizontal => self.fov.to_radians() / aspect_ratio,
+                };
+                let angles = screen * vertical_fov;
+                let theta = angles.length();
+                if theta > PI {
+                    return None;
+                }
+                let side = if theta > 0.0 {
+                    angles / theta * theta.sin()
+                } else {
+                    Vec2::ZERO
+                };
+                Some((
+                    self.position,
+                    forward * theta.cos() + side.x * right + side.y * up,
+                ))
+            }
+            Projection::Panini => {
+                let d = self.panini_distance;
+                let plane = match self.fov_axis {
+                    FovAxis::Vertical => plane,
+                    FovAxis::Horizontal => {
+                        let h = self.fov.to_radians() * 0.5;
+                        let edge = (d + 1.0) * h.sin() / (d + h.cos());
+                        screen * 2.0 * edge / aspect_ratio
+                    }
+                };
+                let k = plane.x * plane.x / ((d + 1.0) * (d + 1.0));
+                let discriminant = k * k * d * d - (k + 1.0) * (k * d * d - 1.0);
+                let cos_longitude = (-k * d + discriminant.sqrt()) / (k + 1.0);
+                let s = (d + 1.0) / (d + cos_longitude);
+                let longitude = plane.x.atan2(s * cos_longitude);
+                let latitude = plane.y.atan2(s);
+                Some((
+                    self.position,
+                    longitude.sin() * latitude.cos() * right
+                        + latitude.sin() * up
+                        + longitude.cos() * latitude.cos() * forward,
+                ))
+            }
+            Projection::Equirectangular => {
+                let (forward, right, up) = self.level_basis();
+                let longitude = uv.x * TAU;
+                let latitude = uv.y * PI;
+                let horizontal = longitude.cos() * forward + longitude.sin() * right;
+                Some((
+                    self.position,
+                    latitude.cos() * horizontal + latitude.sin() * up,
+                ))
+            }
+            Projection::CubeMap => {
+                let (forward, right, up) = self.level_basis();
+                let grid = (uv + 0.5) * Vec2::new(3.0, 2.0);
+                let cell = grid.floor().min(Vec2::new(2.0, 1.0));
+                let local = grid.fract() * 2.0 - 1.0;
+
+                let (face_forward, face_right, face_up) = match (cell.x as u32, cell.y as u32) {
+                    (0, 1) => (forward, right, up),
+                    (1, 1) => (right, -forward, up),
+                    (2, 1) => (-forward, -right, up),
+                    (0, _) => (-right, forward, up),
+                    (1, _) => (up, right, -forward),
+                    _ => (-up, right, forward),
+                };
+                Some((
+                    self.position,
+                    (face_forward + local.x * face_right + local.y * face_up).normalize(),
+                ))
+            }
+        }
+    }
 }
@@ -0,0 +1,461 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::{
+    background::Background,
+    camera::{FovAxis, Projection},
+    fractal::Fractal,
+    marcher::{NormalEstimator, RELAXATION_RANGE},
+    post::{Effect, PostEffect},
+    render_mode::RenderMode,
+    scene::Scene,
+    stereo::StereoMode,
+    timeline,
+    tone_mapping::ToneMapper,
+};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub scene: SceneArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render an image, or a scene's animation, without opening a window
+    Render(RenderArgs),
+}
+
+#[derive(clap::Args)]
+pub struct RenderArgs {
+    /// Where to write the image. `.exr` files keep the full floating point result, anything else
+    /// is written as an 8-bit PNG. Animations number each frame's file, in place of any `#`s in
+    /// the name or else before the extension
+    #[arg(short, long, required_unless_present = "video")]
+    pub output: Option<PathBuf>,
+
+    /// Also write depth, position, normal, ambient occlusion, orbit trap, iteration count and
+    /// alpha layers, for compositing. Needs an `.exr` output
+    #[arg(long)]
+    pub aovs: bool,
+
+    /// Render every frame of the scene's timeline instead of a single image
+    #[arg(long)]
+    pub animation: bool,
+
+    /// Also encode the animation to an MP4 or WebM video with ffmpeg, which must be installed
+    #[arg(long, requires = "animation")]
+    pub video: Option<PathBuf>,
+
+    /// Animation frame rate, defaulting to the timeline's
+    #[arg(long, value_parser = parse_positive)]
+    pub frame_rate: Option<f32>,
+
+    /// Animation length in seconds, defaulting to the timeline's
+    #[arg(long, value_parser = parse_positive)]
+    pub duration: Option<f32>,
+
+    /// Motion blur shutter angle in degrees, from 0 for none to 360 for the whole frame.
+    /// Defaults to the timeline's
+    #[arg(long)]
+    pub shutter_angle: Option<f32>,
+
+    /// Defaults to the scene's width, then 1920. Panoramas default to whatever fits the height,
+    /// then 4096, so equirectangular and cube map projections keep enough detail all the way
+    /// round
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Defaults to the scene's height, then 1080, or whatever fits the width for panoramas
+    #[arg(long)]
+    pub height: Option<u32>,
+
+    /// Also write the scene, at the size rendered, to a scene file to reproduce the image
+    #[arg(long)]
+    pub save_scene: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub scene: SceneArgs,
+}
+
+#[derive(clap::Args)]
+pub struct SceneArgs {
+    /// Scene file to start from, which the other options override
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
+    /// Distance estimator to render
+    #[arg(long, value_enum)]
+    pub fractal: Option<FractalKind>,
+
+    /// Equirectangular `.hdr` or `.exr` image to light the scene with
+    #[arg(long)]
+    pub environment: Option<PathBuf>,
+
+    /// What rays that miss the fractal see. Defaults to the environment when one is given
+    #[arg(long, value_enum)]
+    pub background: Option<BackgroundKind>,
+
+    /// Fog density per unit distance
+    #[arg(long)]
+    pub fog: Option<f32>,
+
+    /// How quickly the fog thins out with height
+    #[arg(long)]
+    pub fog_falloff: Option<f32>,
+
+    /// Glow intensity around near misses
+    #[arg(long)]
+    pub glow: Option<f32>,
+
+    /// Density of the medium scattering light from the light source
+    #[arg(long)]
+    pub scattering: Option<f32>,
+
+    /// Maximum raymarching steps per ray
+    #[arg(long)]
+    pub max_steps: Option<u32>,
+
+    /// Distance after which rays are considered to have escaped
+    #[arg(long)]
+    pub max_distance: Option<f32>,
+
+    /// Hit epsilon, in pixel widths at the hit distance
+    #[arg(long)]
+    pub epsilon: Option<f32>,
+
+    /// Sphere tracing over-relaxation factor, between 1 and 1.9
+    #[arg(long)]
+    pub relaxation: Option<f32>,
+
+    /// How surface normals are estimated
+    #[arg(long, value_enum)]
+    pub normals: Option<NormalEstimatorKind>,
+
+    /// Output a debug view instead of the shaded image
+    #[arg(long, value_enum)]
+    pub mode: Option<RenderModeKind>,
+
+    /// Distance at which the depth view fades to black
+    #[arg(long)]
+    pub depth_range: Option<f32>,
+
+    /// Camera projection
+    #[arg(long, value_enum)]
+    pub projection: Option<ProjectionKind>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, conflicts_with = "hfov")]
+    pub fov: Option<f32>,
+
+    /// Horizontal field of view in degrees
+    #[arg(long)]
+    pub hfov: Option<f32>,
+
+    /// Lens radius for depth of field
+    #[arg(long)]
+    pub aperture: Option<f32>,
+
+    /// Distance to the plane in focus
+    #[arg(long)]
+    pub focus_distance: Option<f32>,
+
+    /// Frames to accumulate
+    #[arg(long)]
+    pub samples: Option<u32>,
+
+    /// Stereo layout
+    #[arg(long, value_enum)]
+    pub stereo: Option<StereoModeKind>,
+
+    /// Distance between the stereo cameras
+    #[arg(long)]
+    pub interocular: Option<f32>,
+
+    /// Distance to the plane with zero parallax
+    #[arg(long)]
+    pub convergence: Option<f32>,
+
+    /// Frame rate the viewer drops resolution to hold while the view changes
+    #[arg(long)]
+    pub target_fps: Option<f32>,
+
+    /// Exposure adjustment in stops
+    #[arg(long, allow_negative_numbers = true)]
+    pub exposure: Option<f32>,
+
+    /// Tone mapping curve
+    #[arg(long, value_enum)]
+    pub tone_mapper: Option<ToneMapperKind>,
+
+    /// `.cube` 3D LUT to grade the tone mapped image with
+    #[arg(long)]
+    pub lut: Option<PathBuf>,
+
+    /// How much of the LUT's grade to apply, from 0 to 1
+    #[arg(long)]
+    pub lut_strength: Option<f32>,
+
+    /// Post processing effects to apply in order, with their default settings
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub post: Vec<EffectKind>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FractalKind {
+    SphereLattice,
+    Sierpinski,
+    Mandelbox,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BackgroundKind {
+    Solid,
+    Gradient,
+    Sky,
+    Environment,
+    Transparent,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum NormalEstimatorKind {
+    Tetrahedral,
+    Central,
+    Analytic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RenderModeKind {
+    Shaded,
+    Normals,
+    Steps,
+    Depth,
+    OrbitTrap,
+    Iterations,
+    Classification,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProjectionKind {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Panini,
+    Equirectangular,
+    Cubemap,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ToneMapperKind {
+    None,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EffectKind {
+    Bloom,
+    Vignette,
+    ColorGrade,
+    ChromaticAberration,
+    Grain,
+    Sharpen,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StereoModeKind {
+    Off,
+    SideBySide,
+    OverUnder,
+    Anaglyph,
+}
+
+/// Width of a panorama with neither size given, as documented on `--width`.
+const PANORAMA_WIDTH: u32 = 4096;
+
+impl RenderArgs {
+    /// Output size, filling in whichever of width and height is missing to suit the projection.
+    pub fn size(&self, scene: &Scene) -> (u32, u32) {
+        // Panoramas fit each eye's view, which stereo layouts put two of in the image
+        let aspect_ratio =
+            scene
+                .camera
+                .projection
+                .aspect_ratio()
+                .map(|aspect| match scene.stereo.mode {
+                    StereoMode::SideBySide => aspect * 2.0,
+                    StereoMode::OverUnder => aspect * 0.5,
+                    StereoMode::Off | StereoMode::Anaglyph => aspect,
+                });
+
+        let width = self.width.or(scene.width);
+        let height = self.height.or(scene.height);
+        match (width, height, aspect_ratio) {
+            (Some(width), Some(height), _) => (width, height),
+            (Some(width), None, Some(aspect)) => (width, (width as f32 / aspect).round() as u32),
+            (None, Some(height), Some(aspect)) => ((height as f32 * aspect).round() as u32, height),
+            (None, None, Some(aspect)) => (
+                PANORAMA_WIDTH,
+                (PANORAMA_WIDTH as f32 / aspect).round() as u32,
+            ),
+            (width, height, None) => (width.unwrap_or(1920), height.unwrap_or(1080)),
+        }
+    }
+}
+
+impl SceneArgs {
+    /// The scene to start from, with the options applied. Fails rather than carrying on with
+    /// something other than what was asked for if a file doesn't load.
+    pub fn scene(&self) -> Result<Scene, String> {
+        let mut scene = match &self.scene {
+            Some(path) => Scene::load(path)
+                .map_err(|error| format!("failed to load scene {}: {error}", path.display()))?,
+            None => Scene::new(),
+        };
+
+        if let Some(kind) = self.fractal {
+            scene.fractal = match kind {
+                FractalKind::SphereLattice => Fractal::SphereLattice,
+                FractalKind::Sierpinski => Fractal::Sierpinski,
+                FractalKind::Mandelbox => Fractal::Mandelbox,
+            };
+        }
+
+        if let Some(path) = &self.environment {
+            scene.environment.load(path).map_err(|error| {
+                format!("failed to load environment {}: {error}", path.display())
+            })?;
+            scene.background = Background::Environment;
+        }
+
+        if let Some(kind) = self.background {
+            scene.background = match kind {
+                BackgroundKind::Solid => Background::default(),
+                BackgroundKind::Gradient => Background::gradient(),
+                BackgroundKind::Sky => Background::sky(),
+                BackgroundKind::Environment => Background::Environment,
+                BackgroundKind::Transparent => Background::Transparent,
+            };
+        }
+
+        let atmosphere = &mut scene.atmosphere;
+        atmosphere.fog_density = self.fog.unwrap_or(atmosphere.fog_density);
+        atmosphere.fog_height_falloff = self.fog_falloff.unwrap_or(atmosphere.fog_height_falloff);
+        atmosphere.glow_intensity = self.glow.unwrap_or(atmosphere.glow_intensity);
+        atmosphere.scattering = self.scattering.unwrap_or(atmosphere.scattering);
+
+        let marcher = &mut scene.marcher;
+        marcher.max_steps = self.max_steps.unwrap_or(marcher.max_steps);
+        marcher.max_distance = self.max_distance.unwrap_or(marcher.max_distance);
+        marcher.pixel_epsilon = self.epsilon.unwrap_or(marcher.pixel_epsilon);
+        marcher.relaxation = self.relaxation.map_or(marcher.relaxation, |relaxation| {
+            relaxation.clamp(*RELAXATION_RANGE.start(), *RELAXATION_RANGE.end())
+        });
+        if let Some(kind) = self.normals {
+            marcher.normal_estimator = match kind {
+                NormalEstimatorKind::Tetrahedral => NormalEstimator::Tetrahedral,
+                NormalEstimatorKind::Central => NormalEstimator::CentralDifferences,
+                NormalEstimatorKind::Analytic => NormalEstimator::Analytic,
+            };
+        }
+
+        if let Some(kind) = self.mode {
+            scene.render_mode = match kind {
+                RenderModeKind::Shaded => RenderMode::Shaded,
+                RenderModeKind::Normals => RenderMode::Normals,
+                RenderModeKind::Steps => RenderMode::Steps,
+                RenderModeKind::Depth => RenderMode::Depth,
+                RenderModeKind::OrbitTrap => RenderMode::OrbitTrap,
+                RenderModeKind::Iterations => RenderMode::Iterations,
+                RenderModeKind::Classification => RenderMode::Classification,
+            };
+        }
+        scene.depth_range = self.depth_range.unwrap_or(scene.depth_range);
+
+        let camera = &mut scene.camera;
+        if let Some(kind) = self.projection {
+            camera.projection = match kind {
+                ProjectionKind::Perspective => Projection::Perspective,
+                ProjectionKind::Orthographic => Projection::Orthographic,
+                ProjectionKind::Fisheye => Projection::Fisheye,
+                ProjectionKind::Panini => Projection::Panini,
+                ProjectionKind::Equirectangular => Projection::Equirectangular,
+                ProjectionKind::Cubemap => Projection::CubeMap,
+            };
+        }
+        if let Some(fov) = self.fov {
+            camera.fov = fov;
+            camera.fov_axis = FovAxis::Vertical;
+        }
+        if let Some(fov) = self.hfov {
+            camera.fov = fov;
+            camera.fov_axis = FovAxis::Horizontal;
+        }
+        camera.aperture = self.aperture.unwrap_or(camera.aperture);
+        camera.focus_distance = self.focus_distance.unwrap_or(camera.focus_distance);
+        scene.samples = self.samples.unwrap_or(scene.samples).max(1);
+
+        let stereo = &mut scene.stereo;
+        if let Some(kind) = self.stereo {
+            stereo.mode = match kind {
+                StereoModeKind::Off => StereoMode::Off,
+                StereoModeKind::SideBySide => StereoMode::SideBySide,
+                StereoModeKind::OverUnder => StereoMode::OverUnder,
+                StereoModeKind::Anaglyph => StereoMode::Anaglyph,
+            };
+        }
+        stereo.interocular_distance = self.interocular.unwrap_or(stereo.interocular_distance);
+        stereo.convergence_distance = self.convergence.unwrap_or(stereo.convergence_distance);
+
+        scene.target_frame_rate = self.target_fps.unwrap_or(scene.target_frame_rate);
+
+        let tone_mapping = &mut scene.tone_mapping;
+        tone_mapping.exposure = self.exposure.unwrap_or(tone_mapping.exposure);
+        if let Some(kind) = self.tone_mapper {
+            tone_mapping.mapper = match kind {
+                ToneMapperKind::None => ToneMapper::None,
+                ToneMapperKind::Reinhard => ToneMapper::Reinhard,
+                ToneMapperKind::Aces => ToneMapper::Aces,
+                ToneMapperKind::Agx => ToneMapper::Agx,
+            };
+        }
+
+        if let Some(path) = &self.lut {
+            scene
+                .look
+                .load(path)
+                .map_err(|error| format!("failed to load LUT {}: {error}", path.display()))?;
+        }
+        let look = &mut scene.look;
+        look.strength = self.lut_strength.unwrap_or(look.strength).clamp(0.0, 1.0);
+
+        for kind in &self.post {
+            let effect = match kind {
+                EffectKind::Bloom => Effect::bloom(),
+                EffectKind::Vignette => Effect::vignette(),
+                EffectKind::ColorGrade => Effect::color_grade(),
+                EffectKind::ChromaticAberration => Effect::chromatic_aberration(),
+                EffectKind::Grain => Effect::grain(),
+                EffectKind::Sharpen => Effect::sharpen(),
+            };
+            scene.post.push(PostEffect::new(effect));
+        }
+
+        Ok(scene)
+    }
+}
+
+/// Parses a frame rate or duration, which need to be above zero.
+fn parse_positive(text: &str) -> Result<f32, String> {
+    let value: f32 = text.parse().map_err(|error| format!("{error}"))?;
+    if !timeline::is_positive(value) {
+        return Err("must be a number above zero".to_owned());
+    }
+    Ok(value)
+}
@@ -0,0 +1,121 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Image based lighting settings for a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Environment {
+    pub path: Option<PathBuf>,
+    pub intensity: f32,
+    /// Rotation about the world up axis, in radians
+    pub rotation: f32,
+    /// Loaded from `path`
+    #[serde(skip)]
+    pub map: Option<Arc<EnvironmentMap>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            path: None,
+            intensity: 1.0,
+            rotation: 0.0,
+            map: None,
+        }
+    }
+}
+
+impl Environment {
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), image::ImageError> {
+        let map = EnvironmentMap::load(path.as_ref())?;
+
+        self.path = Some(path.as_ref().to_owned());
+        self.map = Some(Arc::new(map));
+
+        Ok(())
+    }
+}
+
+/// A single level of an [`EnvironmentMap`]'s mip chain, stored as linear RGBA floats.
+#[derive(Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+/// An equirectangular HDR environment, with a box filtered mip chain.
+///
+/// The lower mips stand in for pre-convolved irradiance and glossy reflection maps, which is
+/// plenty for the kind of lighting a fractal can show off.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    pub levels: Vec<MipLevel>,
+}
+
+impl EnvironmentMap {
+    /// Loads a Radiance `.hdr` or OpenEXR file from disk.
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_rgba32f();
+
+        let mut levels = vec![MipLevel {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        }];
+
+        while let Some(level) = levels.last().and_then(MipLevel::downsample) {
+            levels.push(level);
+        }
+
+        Ok(Self { levels })
+    }
+
+    /// A single black texel, for binding when no environment is loaded.
+    pub fn placeholder() -> Self {
+        Self {
+            levels: vec![MipLevel {
+                width: 1,
+                height: 1,
+                pixels: vec![0.0; 4],
+            }],
+        }
+    }
+}
+
+impl MipLevel {
+    fn downsample(&self) -> Option<Self> {
+        if self.width == 1 && self.height == 1 {
+            return None;
+        }
+
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 4];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(self.width - 1);
+                    let sy = (y * 2 + dy).min(self.height - 1);
+                    let i = ((sy * self.width + sx) * 4) as usize;
+                    for (s, p) in sum.iter_mut().zip(&self.pixels[i..i + 4]) {
+                        *s += p;
+                    }
+                }
+                pixels.extend(sum.map(|c| c * 0.25));
+            }
+        }
+
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Which distance estimator the scene is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Fractal {
+    /// Infinite lattice of spheres, mirrored about the axes
+    #[default]
+    SphereLattice,
+    /// Sierpinski tetrahedron
+    Sierpinski,
+    /// Mandelbox, box and sphere folded
+    Mandelbox,
+}
+
+impl Fractal {
+    pub const ALL: [Self; 3] = [Self::SphereLattice, Self::Sierpinski, Self::Mandelbox];
+
+    /// Index of the matching branch in `shader.wgsl`'s `sdf`.
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::SphereLattice => 0,
+            Self::Sierpinski => 1,
+            Self::Mandelbox => 2,
+        }
+    }
+}
+
+impl fmt::Display for Fractal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::SphereLattice => "Sphere lattice",
+            Self::Sierpinski => "Sierpinski",
+            Self::Mandelbox => "Mandelbox",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Shape of the Sierpinski tetrahedron.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SierpinskiParameters {
+    pub iterations: u32,
+    /// Magnification of each fold towards the nearest corner
+    pub scale: f32,
+}
+
+impl Default for SierpinskiParameters {
+    fn default() -> Self {
+        Self {
+            iterations: 12,
+            scale: 2.0,
+        }
+    }
+}
+
+/// Shape of the Mandelbox.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MandelboxParameters {
+    pub iterations: u32,
+    /// Negative scales give a different, more open family of shapes
+    pub scale: f32,
+    /// Components beyond this are reflected back by the box fold
+    pub fold_limit: f32,
+    /// Points within this radius are scaled up the most by the sphere fold
+    pub min_radius: f32,
+    /// Points within this radius are inverted by the sphere fold
+    pub max_radius: f32,
+}
+
+impl Default for MandelboxParameters {
+    fn default() -> Self {
+        Self {
+            iterations: 39,
+            scale: 3.0,
+            fold_limit: 1.0,
+            min_radius: 0.1,
+            max_radius: 1.0,
+        }
+    }
+}
+
+/// Shape settings of every fractal, so each keeps its own while another is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FractalParameters {
+    pub sierpinski: SierpinskiParameters,
+    pub mandelbox: MandelboxParameters,
+}
@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+use crate::scene::Scene;
+
+/// Edits with the same label closer together than this merge into a single step, so a slider
+/// drag or a flight through the scene undoes in one go.
+const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Oldest steps are dropped past this many.
+const MAX_ENTRIES: usize = 200;
+
+/// A state the scene has been in, and the edit that led to it.
+pub struct Entry {
+    pub label: &'static str,
+    pub scene: Scene,
+    time: Instant,
+    /// Whether further edits with the same label start a new step
+    sealed: bool,
+}
+
+/// Snapshots of the scene after every edit, with a cursor that undo and redo move.
+pub struct History {
+    entries: Vec<Entry>,
+    current: usize,
+    /// Whether an edit with a clear start and end, like a drag, is in progress. Its steps merge
+    /// however far apart they are until it's sealed
+    holding: bool,
+}
+
+impl History {
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            entries: vec![Entry {
+                label: "Opened",
+                scene: scene.clone(),
+                time: Instant::now(),
+                sealed: true,
+            }],
+            current: 0,
+            holding: false,
+        }
+    }
+
+    /// Records the scene after an edit, discarding anything that was undone. Merges into the
+    /// latest step if it's still open and made by the same kind of edit.
+    pub fn record(&mut self, label: &'static str, scene: &Scene) {
+        self.entries.truncate(self.current + 1);
+
+        let now = Instant::now();
+        let latest = self.entries.last_mut().expect("history is never empty");
+        let recent = self.holding || now - latest.time < COALESCE_WINDOW;
+        if latest.label == label && !latest.sealed && recent {
+            latest.scene = scene.clone();
+            latest.time = now;
+            return;
+        }
+
+        self.entries.push(Entry {
+            label,
+            scene: scene.clone(),
+            time: now,
+            sealed: false,
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    /// Closes the latest step to further merging, e.g. once a slider is released.
+    pub fn seal(&mut self) {
+        self.holding = false;
+        if let Some(latest) = self.entries.last_mut() {
+            latest.sealed = true;
+        }
+    }
+
+    /// Starts an edit that lasts until `seal`, like dragging the view around. It gets a step of
+    /// its own, which it stays in however long it pauses for.
+    pub fn begin(&mut self) {
+        self.seal();
+        self.holding = true;
+    }
+
+    pub fn undo(&mut self) -> Option<&Scene> {
+        self.jump(self.current.checked_sub(1)?)
+    }
+
+    pub fn redo(&mut self) -> Option<&Scene> {
+        self.jump(self.current + 1)
+    }
+
+    /// Moves to any step in the history, returning its scene.
+    pub fn jump(&mut self, index: usize) -> Option<&Scene> {
+        let entry = self.entries.get_mut(index)?;
+        entry.sealed = true;
+        self.current = index;
+
+        Some(&entry.scene)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Index of the step the scene is currently at.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(samples: u32) -> Scene {
+        Scene {
+            samples,
+            ..Scene::default()
+        }
+    }
+
+    fn labels(history: &History) -> Vec<&'static str> {
+        history.entries().iter().map(|entry| entry.label).collect()
+    }
+
+    #[test]
+    fn merges_edits_with_the_same_label() {
+        let mut history = History::new(&scene(1));
+        history.record("Exposure", &scene(2));
+        history.record("Exposure", &scene(3));
+
+        assert_eq!(labels(&history), ["Opened", "Exposure"]);
+        assert_eq!(history.entries()[1].scene.samples, 3);
+    }
+
+    #[test]
+    fn keeps_different_edits_and_sealed_ones_apart() {
+        let mut history = History::new(&scene(1));
+        history.record("Exposure", &scene(2));
+        history.record("Fractal", &scene(3));
+        history.seal();
+        history.record("Fractal", &scene(4));
+
+        assert_eq!(
+            labels(&history),
+            ["Opened", "Exposure", "Fractal", "Fractal"]
+        );
+    }
+
+    #[test]
+    fn holds_a_begun_edit_open_until_sealed() {
+        let mut history = History::new(&scene(1));
+        history.record("Camera", &scene(2));
+        history.begin();
+        history.record("Camera", &scene(3));
+        history.entries.last_mut().unwrap().time -= COALESCE_WINDOW * 2;
+        history.record("Camera", &scene(4));
+        history.seal();
+        history.record("Camera", &scene(5));
+
+        assert_eq!(labels(&history), ["Opened", "Camera", "Camera", "Camera"]);
+        assert_eq!(history.entries()[2].scene.samples, 4);
+    }
+
+    #[test]
+    fn undo_and_redo_move_through_the_steps() {
+        let mut history = History::new(&scene(1));
+        history.record("Exposure", &scene(2));
+        history.record("Fractal", &scene(3));
+
+        assert_eq!(history.undo().map(|scene| scene.samples), Some(2));
+        assert_eq!(history.undo().map(|scene| scene.samples), Some(1));
+        assert!(history.undo().is_none());
+        assert!(!history.can_undo());
+        assert_eq!(history.redo().map(|scene| scene.samples), Some(2));
+        assert!(history.can_redo());
+    }
+
+    #[test]
+    fn editing_after_undo_drops_the_undone_steps() {
+        let mut history = History::new(&scene(1));
+        history.record("Exposure", &scene(2));
+        history.record("Fractal", &scene(3));
+        history.undo();
+        history.record("View", &scene(4));
+
+        assert_eq!(labels(&history), ["Opened", "Exposure", "View"]);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn drops_the_oldest_steps_past_the_limit() {
+        let mut history = History::new(&scene(0));
+        for samples in 1..=MAX_ENTRIES as u32 + 10 {
+            let label = if samples % 2 == 0 { "Even" } else { "Odd" };
+            history.record(label, &scene(samples));
+        }
+
+        let entries = history.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].scene.samples, 11);
+        assert_eq!(history.current(), MAX_ENTRIES - 1);
+    }
+}
@@ -0,0 +1,20 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// The point light shading the fractal and scattering through the atmosphere.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Light {
+    pub position: Vec3,
+    /// Linear RGB, above 1 for a brighter light
+    pub color: Vec3,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(-4.0, 0.0, 5.0),
+            color: Vec3::ONE,
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::tone_mapping::{srgb_decode, srgb_encode};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Color grading with a 3D LUT, applied to the display encoded image after tone mapping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Look {
+    pub path: Option<PathBuf>,
+    /// Blend between the tone mapped image at 0 and the fully graded one at 1
+    pub strength: f32,
+    /// Loaded from `path`
+    #[serde(skip)]
+    pub lut: Option<Arc<Lut>>,
+}
+
+impl Default for Look {
+    fn default() -> Self {
+        Self {
+            path: None,
+            strength: 1.0,
+            lut: None,
+        }
+    }
+}
+
+impl Look {
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let lut = Lut::load(path.as_ref())?;
+
+        self.path = Some(path.as_ref().to_owned());
+        self.lut = Some(Arc::new(lut));
+
+        Ok(())
+    }
+
+    /// Grades a linear display color in [0, 1]. Mirrors `grade` in `render.wgsl`.
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let Some(lut) = self.lut.as_ref().filter(|_| self.strength > 0.0) else {
+            return color;
+        };
+
+        let encoded = Vec3::from_array(color.to_array().map(srgb_encode));
+        let graded = encoded.lerp(lut.sample(encoded), self.strength);
+        Vec3::from_array(
+            graded
+                .clamp(Vec3::ZERO, Vec3::ONE)
+                .to_array()
+                .map(srgb_decode),
+        )
+    }
+}
+
+/// A 3D lookup table as read from an Adobe/Resolve `.cube` file.
+#[derive(Debug)]
+pub struct Lut {
+    /// Entries along each axis
+    pub size: u32,
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// `size`³ entries, with red changing fastest and blue slowest
+    pub table: Vec<Vec3>,
+}
+
+impl Lut {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut size: Option<u32> = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut table = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| Error::Parse {
+                line: index + 1,
+                message: message.to_owned(),
+            };
+            let floats = |words: &[&str]| -> Result<Vec<f32>, Error> {
+                words
+                    .iter()
+                    .map(|word| word.parse().map_err(|_| error("expected a number")))
+                    .collect()
+            };
+            let vec3 = |words: &[&str]| match floats(words)?[..] {
+                [r, g, b] => Ok(Vec3::new(r, g, b)),
+                _ => Err(error("expected three numbers")),
+            };
+
+            let words: Vec<_> = line.split_whitespace().collect();
+            let Some((&keyword, arguments)) = words.split_first() else {
+                continue;
+            };
+
+            match keyword {
+                _ if keyword.starts_with('#') => {}
+                "LUT_3D_SIZE" => {
+                    let entries = arguments
+                        .first()
+                        .and_then(|word| word.parse().ok())
+                        .filter(|entries| (2..=256).contains(entries))
+                        .ok_or_else(|| error("size must be between 2 and 256"))?;
+                    size = Some(entries);
+                }
+                "LUT_1D_SIZE" => return Err(error("1D LUTs aren't supported")),
+                "DOMAIN_MIN" => domain_min = vec3(arguments)?,
+                "DOMAIN_MAX" => domain_max = vec3(arguments)?,
+                // Resolve's older spelling of the domain, the same for every channel
+                "LUT_3D_INPUT_RANGE" => match floats(arguments)?[..] {
+                    [min, max] => {
+                        domain_min = Vec3::splat(min);
+                        domain_max = Vec3::splat(max);
+                    }
+                    _ => return Err(error("expected two numbers")),
+                },
+                _ if keyword.parse::<f32>().is_ok() => table.push(vec3(&words)?),
+                // TITLE and any vendor specific keywords
+                _ => {}
+            }
+        }
+
+        let size = size.ok_or_else(|| Error::Parse {
+            line: 0,
+            message: "missing LUT_3D_SIZE".to_owned(),
+        })?;
+        if table.len() != size.pow(3) as usize {
+            return Err(Error::Parse {
+                line: 0,
+                message: format!("expected {} entries, found {}", size.pow(3), table.len()),
+            });
+        }
+        if domain_min.cmpge(domain_max).any() {
+            return Err(Error::Parse {
+                line: 0,
+                message: "DOMAIN_MIN must be below DOMAIN_MAX".to_owned(),
+            });
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Leaves colors unchanged, for binding when no LUT is loaded.
+    pub fn identity() -> Self {
+        let table = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32))
+            .collect();
+
+        Self {
+            size: 2,
+            domain_min: Vec3::ZERO,
+            domain_max: Vec3::ONE,
+            table,
+        }
+    }
+
+    /// Looks up a color with trilinear interpolation, like the GPU's filtering does.
+    pub fn sample(&self, color: Vec3) -> Vec3 {
+        let last = self.size - 1;
+        let position = ((color - self.domain_min) / (self.domain_max - self.domain_min))
+            .clamp(Vec3::ZERO, Vec3::ONE)
+            * last as f32;
+        let low = position.floor().min(Vec3::splat((last - 1) as f32));
+        let t = position - low;
+
+        let entry =
+            |x: u32, y: u32, z: u32| self.table[((z * self.size + y) * self.size + x) as usize];
+        let [x, y, z] = low.to_array().map(|c| c as u32);
+
+        let lerp_x = |y, z| entry(x, y, z).lerp(entry(x + 1, y, z), t.x);
+        let lerp_y = |z| lerp_x(y, z).lerp(lerp_x(y + 1, z), t.y);
+        lerp_y(z).lerp(lerp_y(z + 1), t.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2×2×2 `.cube` file of the identity, with a title and comments.
+    const IDENTITY: &str = "\
+TITLE \"identity\"
+# red changes fastest
+LUT_3D_SIZE 2
+
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match Lut::parse(text) {
+            Err(Error::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_identity() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.table, Lut::identity().table);
+    }
+
+    #[test]
+    fn identity_sample_leaves_colors_alone() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+
+        for color in [
+            Vec3::ZERO,
+            Vec3::ONE,
+            Vec3::new(0.25, 0.5, 0.75),
+            Vec3::new(1.0, 0.0, 0.3),
+        ] {
+            assert!(lut.sample(color).abs_diff_eq(color, 1e-6), "{color}");
+        }
+    }
+
+    #[test]
+    fn sample_clamps_to_the_domain() {
+        let lut = Lut::identity();
+
+        assert_eq!(
+            lut.sample(Vec3::new(-1.0, 2.0, 0.5)),
+            Vec3::new(0.0, 1.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn reads_the_input_range() {
+        let text = IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 2");
+        let lut = Lut::parse(&text).unwrap();
+
+        assert_eq!(lut.domain_max, Vec3::splat(2.0));
+        assert!(lut
+            .sample(Vec3::splat(1.0))
+            .abs_diff_eq(Vec3::splat(0.5), 1e-6));
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(parse_error("LUT_3D_SIZE 1\n").0, 1);
+        assert_eq!(parse_error("# 1D\nLUT_1D_SIZE 16\n").0, 2);
+        assert_eq!(
+            parse_error(&IDENTITY.replace("1 1 1", "1 one 1")),
+            (12, "expected a number".to_owned())
+        );
+        assert_eq!(
+            parse_error(&IDENTITY.replace("0 1 1", "0 1")),
+            (11, "expected three numbers".to_owned())
+        );
+    }
+
+    #[test]
+    fn reports_missing_size_and_entries() {
+        assert_eq!(parse_error("0 0 0\n").1, "missing LUT_3D_SIZE");
+        assert_eq!(
+            parse_error(&IDENTITY.replace("1 1 1\n", "")).1,
+            "expected 8 entries, found 7"
+        );
+        assert_eq!(
+            parse_error(&IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MIN 1 1 1")).1,
+            "DOMAIN_MIN must be below DOMAIN_MAX"
+        );
+    }
+}
@@ -1,12 +1,91 @@
+use std::{error::Error, process::ExitCode};
+
+use clap::Parser;
 use iced::{Application, Settings};
 
+mod animation;
+mod aov;
 mod app;
+mod atmosphere;
+mod background;
+mod bookmark;
 mod camera;
+mod cli;
+mod environment;
+mod fractal;
+mod history;
+mod light;
+mod lut;
+mod marcher;
+mod material;
+mod offline;
+mod post;
+mod presets;
+mod render_mode;
 mod scene;
+// Only the active distance estimator is referenced, the rest are kept in step with the shader
+#[allow(dead_code)]
 mod sdf;
 mod shader;
-mod vec3_input;
+mod stereo;
+mod timeline;
+mod tone_mapping;
+mod vec_input;
+
+pub fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = cli::Args::parse();
+
+    match args.command {
+        Some(cli::Command::Render(render)) => {
+            // Checked before anything is written, so a rejected command leaves no files behind
+            if render.aovs && !render.output.as_deref().is_some_and(offline::is_exr) {
+                return Err("AOVs can only be written to an .exr output".into());
+            }
+
+            let mut scene = render.scene.scene()?;
+            let timeline = &mut scene.timeline;
+            timeline.frame_rate = render.frame_rate.unwrap_or(timeline.frame_rate);
+            timeline.duration = render.duration.unwrap_or(timeline.duration);
+            timeline.shutter_angle = render
+                .shutter_angle
+                .map_or(timeline.shutter_angle, |angle| angle.clamp(0.0, 360.0));
+            // Kept with the scene, so what's saved or embedded in a PNG renders at the same size
+            let (width, height) = render.size(&scene);
+            scene.width = Some(width);
+            scene.height = Some(height);
+            if let Some(path) = &render.save_scene {
+                scene.save(path)?;
+            }
+
+            let renderer = offline::OfflineRenderer::new()?;
+            if render.animation {
+                let output = animation::Output {
+                    frames: render.output.as_deref(),
+                    video: render.video.as_deref(),
+                    aovs: render.aovs,
+                };
+                animation::render(&renderer, &scene, width, height, output)?;
+            } else if let Some(output) = render.output.as_ref().filter(|_| render.aovs) {
+                let render = renderer.render_with_aovs(&scene, width, height)?;
+                let aovs = render.aovs.expect("AOVs were rendered");
+                aov::save(output, &render.image, &aovs)?;
+            } else if let Some(output) = &render.output {
+                let image = renderer.render(&scene, width, height)?;
+                offline::save(&image, output, &scene)?;
+            }
+        }
+        None => app::App::run(Settings::with_flags(args.scene.scene()?))?,
+    }
 
-pub fn main() -> iced::Result {
-    app::App::run(Settings::default())
+    Ok(())
 }
@@ -0,0 +1,63 @@
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+
+/// Over-relaxation factors that the CLI and the panel accept. Much past 1.9, steps overshoot so
+/// often that marching gets slower rather than faster.
+pub const RELAXATION_RANGE: RangeInclusive<f32> = 1.0..=1.9;
+
+/// How surface normals are estimated at hit points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NormalEstimator {
+    /// Four samples on the corners of a tetrahedron
+    #[default]
+    Tetrahedral,
+    /// Six samples, two along each axis
+    CentralDifferences,
+    /// Gradient carried through the distance estimator itself. Exact and cheap for the DEs that
+    /// support it
+    Analytic,
+}
+
+impl NormalEstimator {
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Tetrahedral => 0,
+            Self::CentralDifferences => 1,
+            Self::Analytic => 2,
+        }
+    }
+}
+
+/// Raymarching quality controls.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Marcher {
+    pub max_steps: u32,
+    pub max_distance: f32,
+    /// Lower bound on the hit epsilon, for surfaces right in front of the camera
+    pub min_distance: f32,
+    /// Lower bound on the finite difference step, which otherwise follows the hit epsilon
+    pub normal_sampling_distance: f32,
+    pub normal_estimator: NormalEstimator,
+    /// Hit epsilon in pixels. Surfaces are considered hit once they're closer than this many
+    /// pixel widths at the current distance
+    pub pixel_epsilon: f32,
+    /// Over-relaxation factor for sphere tracing. 1.0 is plain sphere tracing, values up to
+    /// around 1.9 take bigger steps through open space
+    pub relaxation: f32,
+}
+
+impl Default for Marcher {
+    fn default() -> Self {
+        Self {
+            max_steps: 1000,
+            max_distance: 1000.0,
+            min_distance: 0.000001,
+            normal_sampling_distance: 0.000001,
+            normal_estimator: NormalEstimator::default(),
+            pixel_epsilon: 1.0,
+            relaxation: 1.2,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Blinn-Phong surface the fractal is shaded with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    /// Linear RGB
+    pub diffuse_color: Vec3,
+    /// Linear RGB
+    pub specular_color: Vec3,
+    /// Specular exponent. Higher gives smaller highlights and sharper environment reflections
+    pub shininess: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            diffuse_color: Vec3::splat(0.5),
+            specular_color: Vec3::ONE,
+            shininess: 1.0,
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Headless rendering, for exporting images without opening a window.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use glam::Vec3;
+use half::f16;
+use iced::widget::shader::wgpu;
+use image::{DynamicImage, ImageFormat, Rgba, Rgba32FImage, RgbaImage};
+
+use crate::{
+    aov::Aovs,
+    lut::Look,
+    scene::{self, Scene, PNG_SCENE_KEYWORD},
+    shader::{
+        pipeline::{ComputeShaderPipeline, Uniforms},
+        post::PostProcessPipeline,
+    },
+    tone_mapping::{srgb_encode, ToneMapping},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    BufferMap(wgpu::BufferAsyncError),
+    Image(image::ImageError),
+    Io(io::Error),
+    Png(png::EncodingError),
+    Scene(scene::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            Self::RequestDevice(error) => write!(f, "failed to create device: {error}"),
+            Self::BufferMap(error) => write!(f, "failed to read back render: {error}"),
+            Self::Image(error) => write!(f, "failed to write image: {error}"),
+            Self::Io(error) => write!(f, "failed to write image: {error}"),
+            Self::Png(error) => write!(f, "failed to write image: {error}"),
+            Self::Scene(error) => write!(f, "failed to embed scene: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A finished render, with its AOVs if they were asked for.
+pub struct Render {
+    /// Linear, with premultiplied alpha
+    pub image: Rgba32FImage,
+    pub aovs: Option<Aovs>,
+}
+
+pub struct OfflineRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl OfflineRenderer {
+    pub fn new() -> Result<Self, Error> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or(Error::NoAdapter)?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("offline device"),
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::default(),
+            },
+            None,
+        ))
+        .map_err(Error::RequestDevice)?;
+
+        Ok(Self { device, queue })
+    }
+
+    /// Renders every sample of the scene, returning the linear result with premultiplied alpha.
+    pub fn render(&self, scene: &Scene, width: u32, height: u32) -> Result<Rgba32FImage, Error> {
+        let uniforms = Uniforms::new(scene);
+        self.render_samples(scene, width, height, false, |_| uniforms)
+            .map(|render| render.image)
+    }
+
+    /// Renders the scene along with its AOVs.
+    pub fn render_with_aovs(
+        &self,
+        scene: &Scene,
+        width: u32,
+        height: u32,
+    ) -> Result<Render, Error> {
+        let uniforms = Uniforms::new(scene);
+        self.render_samples(scene, width, height, true, |_| uniforms)
+    }
+
+    /// Renders the frame of the scene's timeline at `time` seconds. Samples are spread over the
+    /// time the shutter is open, centred on `time`, so anything animated is motion blurred.
+    pub fn render_frame(
+        &self,
+        scene: &Scene,
+        time: f32,
+        width: u32,
+        height: u32,
+        aovs: bool,
+    ) -> Result<Render, Error> {
+        let timeline = &scene.timeline;
+        let shutter = timeline.shutter_angle / 360.0 / timeline.frame_rate;
+
+        let mut frame = scene.clone();
+        frame.seek(time);
+        let mut sub_frame = frame.clone();
+        self.render_samples(&frame, width, height, aovs, |sample| {
+            let offset = (sample as f32 + 0.5) / scene.samples as f32 - 0.5;
+            sub_frame.seek(time + offset * shutter);
+            Uniforms::new(&sub_frame)
+        })
+    }
+
+    /// Accumulates `scene.samples` samples, each with the uniforms given for its index. Post
+    /// processing uses `scene` as it is.
+    fn render_samples(
+        &self,
+        scene: &Scene,
+        width: u32,
+        height: u32,
+        aovs: bool,
+        mut uniforms: impl FnMut(u32) -> Uniforms,
+    ) -> Result<Render, Error> {
+        let size = iced::Size::new(width, height);
+        let mut pipeline = ComputeShaderPipeline::new(&self.device, &self.queue, size);
+        pipeline.set_environment(&self.device, &self.queue, scene.environment.map.as_ref());
+        if aovs {
+            pipeline.enable_aovs(&self.device);
+        }
+
+        for sample in 0..scene.samples {
+            pipeline.accumulate(&self.queue, &uniforms(sample));
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("offline command encoder"),
+                });
+            pipeline.dispatch(&mut encoder);
+            self.queue.submit(Some(encoder.finish()));
+        }
+
+        let aovs = match pipeline.aov_buffer() {
+            Some(buffer) => Some(Aovs::from_sums(width, height, &self.read(buffer)?)),
+            None => None,
+        };
+
+        if scene.post.iter().any(|effect| effect.enabled) {
+            let image = self.post_process(scene, &pipeline)?;
+            return Ok(Render { image, aovs });
+        }
+
+        // Read back the accumulated sum rather than the half precision screen texture, so nothing
+        // is lost for formats that can hold it
+        let scale = 1.0 / pipeline.frames() as f32;
+        let pixels = self
+            .read(pipeline.accumulation_buffer())?
+            .into_iter()
+            .map(|sum| sum * scale)
+            .collect();
+        let image =
+            Rgba32FImage::from_raw(width, height, pixels).expect("accumulation buffer size");
+
+        Ok(Render { image, aovs })
+    }
+
+    /// Copies a storage buffer of floats back from the GPU.
+    fn read(&self, source: &wgpu::Buffer) -> Result<Vec<f32>, Error> {
+        let buffer = self.readback_buffer(source.size());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("offline readback encoder"),
+            });
+        encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+        self.queue.submit(Some(encoder.finish()));
+        self.map(&buffer)?;
+
+        let data = buffer.slice(..).get_mapped_range();
+        Ok(bytemuck::cast_slice(&data).to_vec())
+    }
+
+    /// Runs the scene's post processing stack on a finished render and reads back the result.
+    fn post_process(
+        &self,
+        scene: &Scene,
+        pipeline: &ComputeShaderPipeline,
+    ) -> Result<Rgba32FImage, Error> {
+        let size = pipeline.size();
+        let mut post = PostProcessPipeline::new(&self.device, size);
+        post.update(&self.queue, &scene.post, size);
+
+        // Texture copies need rows padded to a multiple of 256 bytes
+        const PIXEL_SIZE: u32 = 8;
+        let row_size =
+            (size.width * PIXEL_SIZE).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.readback_buffer((row_size * size.height) as u64);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("offline post encoder"),
+            });
+        post.process(&mut encoder, &pipeline.screen_texture);
+        encoder.copy_texture_to_buffer(
+            post.output_texture().as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_size),
+                    rows_per_image: None,
+                },
+            },
+            post.output_texture().size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+        self.map(&buffer)?;
+
+        let data = buffer.slice(..).get_mapped_range();
+        let pixels = data
+            .chunks_exact(row_size as usize)
+            .flat_map(|row| {
+                bytemuck::cast_slice::<u8, f16>(&row[..(size.width * PIXEL_SIZE) as usize])
+                    .iter()
+                    .map(|channel| channel.to_f32())
+            })
+            .collect();
+
+        Ok(Rgba32FImage::from_raw(size.width, size.height, pixels).expect("post texture size"))
+    }
+
+    fn readback_buffer(&self, size: u64) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offline readback buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Blocks until `buffer` is mapped for reading.
+    fn map(&self, buffer: &wgpu::Buffer) -> Result<(), Error> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("map_async callback dropped")
+            .map_err(Error::BufferMap)
+    }
+}
+
+/// Writes a render of `scene`, picking the format from the file extension. OpenEXR keeps the
+/// linear floating point values with premultiplied alpha, anything else is tone mapped and graded
+/// to an 8-bit sRGB PNG with the scene embedded, so it can be opened again from the image.
+pub fn save(image: &Rgba32FImage, path: &Path, scene: &Scene) -> Result<(), Error> {
+    if is_exr(path) {
+        return DynamicImage::ImageRgba32F(image.clone())
+            .save_with_format(path, ImageFormat::OpenExr)
+            .map_err(Error::Image);
+    }
+
+    let display_image = to_display(image, &scene.display_tone_mapping(), &scene.display_look());
+
+    let file = BufWriter::new(File::create(path).map_err(Error::Io)?);
+    let mut encoder = png::Encoder::new(file, display_image.width(), display_image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .add_itxt_chunk(
+            PNG_SCENE_KEYWORD.to_owned(),
+            scene
+                .to_ron(path.parent().unwrap_or(Path::new("")))
+                .map_err(Error::Scene)?,
+        )
+        .map_err(Error::Png)?;
+
+    let mut writer = encoder.write_header().map_err(Error::Png)?;
+    writer
+        .write_image_data(display_image.as_raw())
+        .map_err(Error::Png)?;
+    writer.finish().map_err(Error::Png)
+}
+
+/// The 8-bit sRGB image a PNG gets, with straight alpha.
+pub fn to_display(image: &Rgba32FImage, tone_mapping: &ToneMapping, look: &Look) -> RgbaImage {
+    let mut display_image = RgbaImage::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        display_image.put_pixel(x, y, display(pixel, tone_mapping, look));
+    }
+    display_image
+}
+
+pub fn is_exr(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+}
+
+/// Tone maps, grades and encodes a pixel for display. The shader averages transparent background
+/// samples in as zero, so edges against a transparent background come out premultiplied and need
+/// unpremultiplying first.
+fn display(pixel: &Rgba<f32>, tone_mapping: &ToneMapping, look: &Look) -> Rgba<u8> {
+    let [r, g, b, a] = pixel.0;
+    let a = a.clamp(0.0, 1.0);
+    let scale = if a > 0.0 { 1.0 / a } else { 1.0 };
+
+    let color = look.apply(tone_mapping.apply(Vec3::new(r, g, b) * scale));
+    let channel = |c: f32| (srgb_encode(c) * 255.0).round() as u8;
+    Rgba([
+        channel(color.x),
+        channel(color.y),
+        channel(color.z),
+        (a * 255.0).round() as u8,
+    ])
+}
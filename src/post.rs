@@ -0,0 +1,213 @@
+use std::{fmt, ops::RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+
+/// One step of the post processing stack. The stack runs in order on the linear HDR image,
+/// before tone mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    /// Glow spreading out from anything brighter than `threshold`
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        /// Spread of each upsampling step, in texels
+        radius: f32,
+    },
+    /// Darkens towards the corners
+    Vignette { intensity: f32, smoothness: f32 },
+    /// Lift, gamma and gain curves, then contrast around middle grey and saturation
+    ColorGrade {
+        lift: f32,
+        gamma: f32,
+        gain: f32,
+        contrast: f32,
+        saturation: f32,
+    },
+    /// Pushes red and blue apart towards the edges, like a cheap lens
+    ChromaticAberration {
+        /// Offset at the corners, as a percentage of the image size
+        strength: f32,
+    },
+    /// Static film grain, strongest in the mid tones
+    Grain { intensity: f32, size: f32 },
+    /// Unsharp mask
+    Sharpen { strength: f32 },
+}
+
+/// A slider's worth of an [`Effect`].
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: f32,
+    pub range: RangeInclusive<f32>,
+}
+
+impl Effect {
+    /// Every effect, with sensible starting values.
+    pub const ALL: [Self; 6] = [
+        Self::bloom(),
+        Self::vignette(),
+        Self::color_grade(),
+        Self::chromatic_aberration(),
+        Self::grain(),
+        Self::sharpen(),
+    ];
+
+    pub const fn bloom() -> Self {
+        Self::Bloom {
+            threshold: 1.0,
+            intensity: 0.5,
+            radius: 1.0,
+        }
+    }
+
+    pub const fn vignette() -> Self {
+        Self::Vignette {
+            intensity: 0.5,
+            smoothness: 0.5,
+        }
+    }
+
+    /// Neutral grade, changing nothing until adjusted.
+    pub const fn color_grade() -> Self {
+        Self::ColorGrade {
+            lift: 0.0,
+            gamma: 1.0,
+            gain: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+
+    pub const fn chromatic_aberration() -> Self {
+        Self::ChromaticAberration { strength: 0.5 }
+    }
+
+    pub const fn grain() -> Self {
+        Self::Grain {
+            intensity: 0.1,
+            size: 1.5,
+        }
+    }
+
+    pub const fn sharpen() -> Self {
+        Self::Sharpen { strength: 0.5 }
+    }
+
+    /// Index of the matching branch in `post.wgsl`'s `fs_main`.
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Bloom { .. } => 0,
+            Self::Vignette { .. } => 1,
+            Self::ColorGrade { .. } => 2,
+            Self::ChromaticAberration { .. } => 3,
+            Self::Grain { .. } => 4,
+            Self::Sharpen { .. } => 5,
+        }
+    }
+
+    fn fields_mut(&mut self) -> Vec<&mut f32> {
+        match self {
+            Self::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => vec![threshold, intensity, radius],
+            Self::Vignette {
+                intensity,
+                smoothness,
+            } => vec![intensity, smoothness],
+            Self::ColorGrade {
+                lift,
+                gamma,
+                gain,
+                contrast,
+                saturation,
+            } => vec![lift, gamma, gain, contrast, saturation],
+            Self::ChromaticAberration { strength } => vec![strength],
+            Self::Grain { intensity, size } => vec![intensity, size],
+            Self::Sharpen { strength } => vec![strength],
+        }
+    }
+
+    /// Names and slider ranges, in the same order as `fields_mut`.
+    fn field_info(&self) -> &'static [(&'static str, RangeInclusive<f32>)] {
+        match self {
+            Self::Bloom { .. } => &[
+                ("Threshold", 0.0..=10.0),
+                ("Intensity", 0.0..=2.0),
+                ("Radius", 0.5..=4.0),
+            ],
+            Self::Vignette { .. } => &[("Intensity", 0.0..=1.0), ("Smoothness", 0.05..=1.0)],
+            Self::ColorGrade { .. } => &[
+                ("Lift", -0.5..=0.5),
+                ("Gamma", 0.2..=3.0),
+                ("Gain", 0.0..=4.0),
+                ("Contrast", 0.0..=3.0),
+                ("Saturation", 0.0..=3.0),
+            ],
+            Self::ChromaticAberration { .. } => &[("Strength", 0.0..=5.0)],
+            Self::Grain { .. } => &[("Intensity", 0.0..=1.0), ("Size", 1.0..=8.0)],
+            Self::Sharpen { .. } => &[("Strength", 0.0..=2.0)],
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<Parameter> {
+        let mut effect = *self;
+        effect
+            .fields_mut()
+            .into_iter()
+            .zip(self.field_info())
+            .map(|(value, (name, range))| Parameter {
+                name,
+                value: *value,
+                range: range.clone(),
+            })
+            .collect()
+    }
+
+    pub fn set_parameter(&mut self, index: usize, value: f32) {
+        if let Some(field) = self.fields_mut().into_iter().nth(index) {
+            *field = value;
+        }
+    }
+
+    /// Parameter values in order, padded with zeroes, as `post.wgsl` reads them.
+    pub fn values(&self) -> [f32; 8] {
+        let mut values = [0.0; 8];
+        let mut effect = *self;
+        for (value, field) in values.iter_mut().zip(effect.fields_mut()) {
+            *value = *field;
+        }
+        values
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Bloom { .. } => "Bloom",
+            Self::Vignette { .. } => "Vignette",
+            Self::ColorGrade { .. } => "Color grade",
+            Self::ChromaticAberration { .. } => "Chromatic aberration",
+            Self::Grain { .. } => "Film grain",
+            Self::Sharpen { .. } => "Sharpen",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// An effect in the stack, which can be switched off without losing its settings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PostEffect {
+    pub enabled: bool,
+    pub effect: Effect,
+}
+
+impl PostEffect {
+    pub fn new(effect: Effect) -> Self {
+        Self {
+            enabled: true,
+            effect,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+struct Pass {
+    // Fraction of the input texture rendered to, from the top left
+    region: vec2f,
+    // Size of the texture being rendered to, in pixels
+    output_size: vec2f,
+    // Effect index from `Effect::index`, or one of the bloom steps below
+    kind: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+    // Effect parameters, in the order `Effect::values` lists them
+    params: array<vec4f, 2>,
+}
+
+// Bloom's internal steps, after the effects
+const BLOOM_DOWNSAMPLE = 16u;
+// Like the downsample, but keeping only highlights
+const BLOOM_PREFILTER = 17u;
+const BLOOM_UPSAMPLE = 18u;
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var samp: sampler;
+@group(0) @binding(2) var<uniform> settings: Pass;
+// The top of the bloom chain, for compositing. A placeholder for every other pass
+@group(0) @binding(3) var bloom: texture_2d<f32>;
+
+// Vertex shader - generates full-screen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) vert_idx: u32) -> @builtin(position) vec4<f32> {
+    // Generate clip-space coordinates directly
+    let x = f32(vert_idx & 1) * 4.0 - 1.0;
+    let y = f32(vert_idx >> 1) * 4.0 - 1.0;
+    return vec4(x, y, 0.0, 1.0);
+}
+
+fn param(index: u32) -> f32 {
+    return settings.params[index / 4u][index % 4u];
+}
+
+// Samples the input at `uv` across the whole output, stretching the rendered region over it and
+// stopping half a texel short of its edge so nothing outside bleeds in
+fn sample_input(uv: vec2f) -> vec4f {
+    let half_texel = 0.5 / vec2f(textureDimensions(input));
+    let clamped = clamp(uv * settings.region, half_texel, settings.region - half_texel);
+    return textureSampleLevel(input, samp, clamped, 0.0);
+}
+
+fn luminance(color: vec3f) -> f32 {
+    return dot(color, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+fn hash(p: vec2u) -> f32 {
+    var h = p.x * 747796405u + p.y * 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    return f32((h >> 22u) ^ h) / 4294967296.0;
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = pos.xy / settings.output_size;
+    let texel = 1.0 / settings.output_size;
+    let color = sample_input(uv);
+
+    switch settings.kind {
+        // Bloom composite
+        case 0u: {
+            let glow = textureSampleLevel(bloom, samp, uv, 0.0).rgb;
+            return vec4f(color.rgb + glow * param(1u), color.a);
+        }
+        // Vignette
+        case 1u: {
+            let distance = length(uv - 0.5) * sqrt(2.0);
+            let shade = 1.0 - param(0u) * smoothstep(1.0 - param(1u), 1.0, distance);
+            return vec4f(color.rgb * shade, color.a);
+        }
+        // Color grade
+        case 2u: {
+            var graded = param(2u) * (color.rgb + param(0u) * (1.0 - color.rgb));
+            graded = pow(max(graded, vec3f(0.0)), vec3f(1.0 / param(1u)));
+            graded = 0.18 * pow(graded / 0.18, vec3f(param(3u)));
+            graded = mix(vec3f(luminance(graded)), graded, param(4u));
+            return vec4f(max(graded, vec3f(0.0)), color.a);
+        }
+        // Chromatic aberration
+        case 3u: {
+            let offset = (uv - 0.5) * param(0u) * 0.01;
+            let red = sample_input(uv + offset).r;
+            let blue = sample_input(uv - offset).b;
+            return vec4f(red, color.g, blue, color.a);
+        }
+        // Film grain
+        case 4u: {
+            let cell = vec2u(pos.xy / param(1u));
+            let noise = hash(cell) - 0.5;
+            // Scale by a parabola over the tone, so blacks and highlights stay clean
+            let tone = saturate(luminance(color.rgb));
+            let amount = noise * param(0u) * 4.0 * tone * (1.0 - tone);
+            return vec4f(color.rgb * (1.0 + amount), color.a);
+        }
+        // Sharpen
+        case 5u: {
+            let neighbours = sample_input(uv + vec2f(texel.x, 0.0)) +
+                             sample_input(uv - vec2f(texel.x, 0.0)) +
+                             sample_input(uv + vec2f(0.0, texel.y)) +
+                             sample_input(uv - vec2f(0.0, texel.y));
+            let sharpened = color.rgb + (color.rgb - neighbours.rgb * 0.25) * param(0u);
+            return vec4f(max(sharpened, vec3f(0.0)), color.a);
+        }
+        case BLOOM_DOWNSAMPLE, BLOOM_PREFILTER: {
+            // Four bilinear taps cover a 4x4 block of the input
+            let input_texel = 1.0 / vec2f(textureDimensions(input)) / settings.region;
+            var sum = vec3f(0.0);
+            for (var i = 0; i < 4; i++) {
+                let corner = vec2f(f32(i & 1), f32(i >> 1u)) * 2.0 - 1.0;
+                var tap = sample_input(uv + corner * input_texel).rgb;
+                if settings.kind == BLOOM_PREFILTER {
+                    // Keep only what's above the threshold, without shifting the hue
+                    let brightness = luminance(tap);
+                    tap *= max(brightness - param(0u), 0.0) / max(brightness, 1e-4);
+                }
+                sum += tap;
+            }
+            return vec4f(sum * 0.25, 1.0);
+        }
+        case BLOOM_UPSAMPLE: {
+            // 3x3 tent, added onto the level below by the pipeline's blend state
+            let input_texel = param(2u) / vec2f(textureDimensions(input));
+            var sum = vec3f(0.0);
+            for (var y = -1; y <= 1; y++) {
+                for (var x = -1; x <= 1; x++) {
+                    let weight = f32((2 - abs(x)) * (2 - abs(y))) / 16.0;
+                    sum += sample_input(uv + vec2f(f32(x), f32(y)) * input_texel).rgb * weight;
+                }
+            }
+            return vec4f(sum, 1.0);
+        }
+        default: {
+            return color;
+        }
+    }
+}
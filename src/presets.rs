@@ -0,0 +1,126 @@
+// Scenes bundled with the app, with thumbnails rendered offline and cached on disk.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    offline::{self, OfflineRenderer},
+    scene::{self, Scene},
+};
+
+const THUMBNAIL_WIDTH: u32 = 192;
+const THUMBNAIL_HEIGHT: u32 = 108;
+const THUMBNAIL_SAMPLES: u32 = 16;
+
+/// Everything besides the scene that decides what a thumbnail looks like, so a change to the
+/// renderer renders them again rather than showing stale ones.
+const RENDERER_SOURCES: [&str; 4] = [
+    env!("CARGO_PKG_VERSION"),
+    include_str!("shader.wgsl"),
+    include_str!("post.wgsl"),
+    include_str!("render.wgsl"),
+];
+
+pub struct Preset {
+    pub name: &'static str,
+    /// Contents of the scene file
+    source: &'static str,
+}
+
+pub const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "Sphere lattice",
+        source: include_str!("../presets/sphere_lattice.ron"),
+    },
+    Preset {
+        name: "Sierpinski",
+        source: include_str!("../presets/sierpinski.ron"),
+    },
+    Preset {
+        name: "Mandelbox",
+        source: include_str!("../presets/mandelbox.ron"),
+    },
+    Preset {
+        name: "Mandelbox interior",
+        source: include_str!("../presets/mandelbox_interior.ron"),
+    },
+];
+
+impl Preset {
+    pub fn scene(&self) -> Result<Scene, scene::Error> {
+        Scene::parse(self.source)
+    }
+
+    /// Where the thumbnail is cached. Named after a hash of the scene file and the shaders, so
+    /// editing a preset or the renderer renders it again.
+    fn thumbnail_path(&self) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        self.source.hash(&mut hasher);
+        RENDERER_SOURCES.hash(&mut hasher);
+        (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, THUMBNAIL_SAMPLES).hash(&mut hasher);
+
+        cache_dir().join(format!("{:016x}.png", hasher.finish()))
+    }
+}
+
+/// Renders whichever thumbnails aren't cached yet, returning the path to each preset's, or
+/// `None` where it couldn't be rendered. Blocks until they're all done.
+pub fn thumbnails() -> Vec<Option<PathBuf>> {
+    let mut renderer = None;
+
+    PRESETS
+        .iter()
+        .map(|preset| {
+            let path = preset.thumbnail_path();
+            if path.exists() {
+                return Some(path);
+            }
+
+            let result = render_thumbnail(&mut renderer, preset, &path);
+
+            match result {
+                Ok(()) => Some(path),
+                Err(error) => {
+                    eprintln!("Failed to render thumbnail for {}: {error}", preset.name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Renders a preset to `path`, creating the renderer the first time it's needed.
+fn render_thumbnail(
+    renderer: &mut Option<OfflineRenderer>,
+    preset: &Preset,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let renderer = match renderer {
+        Some(renderer) => renderer,
+        None => renderer.insert(OfflineRenderer::new()?),
+    };
+
+    let mut scene = preset.scene()?;
+    scene.samples = THUMBNAIL_SAMPLES;
+    let image = renderer.render(&scene, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)?;
+
+    fs::create_dir_all(cache_dir())?;
+    offline::save(&image, path, &scene)?;
+
+    Ok(())
+}
+
+/// `$XDG_CACHE_HOME/fractals/thumbnails`, falling back to `~/.cache` and then the temporary
+/// directory.
+fn cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("fractals")
+        .join("thumbnails")
+}
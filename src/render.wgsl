@@ -1,5 +1,28 @@
+struct Display {
+    // Fraction of the screen texture rendered to, from the top left
+    region: vec2f,
+    // Stops, applied before tone mapping
+    exposure: f32,
+    // 0 clips, then Reinhard, ACES and AgX
+    tone_mapper: u32,
+    // 1 unless the target's format encodes sRGB itself
+    encode_srgb: u32,
+    _padding: u32,
+    // Top left of the view in the target, in pixels
+    origin: vec2f,
+    // Input range the LUT covers
+    lut_domain_min: vec3f,
+    // Entries along each axis of the LUT
+    lut_size: f32,
+    lut_domain_max: vec3f,
+    // 0 when no LUT is loaded
+    lut_strength: f32,
+}
+
 @group(0) @binding(0) var screen: texture_2d<f32>;
 @group(0) @binding(1) var samp: sampler;
+@group(0) @binding(2) var<uniform> display: Display;
+@group(0) @binding(3) var lut: texture_3d<f32>;
 
 // Vertex shader - generates full-screen triangle
 @vertex
@@ -16,7 +39,11 @@ fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
     // Viewport resolution (in pixels)
     let screen_size = textureDimensions(screen);
 
-    let uv = pos.xy / vec2f(screen_size);
+    // Stretch the rendered region over the whole view, letting the sampler filter the upscale.
+    // Stop half a texel short of the edge so nothing outside the region bleeds in
+    let half_texel = 0.5 / vec2f(screen_size);
+    let view_pos = pos.xy - display.origin;
+    let uv = min(view_pos / vec2f(screen_size) * display.region, display.region - half_texel);
 
     let color = textureSample(screen, samp, uv.xy);
 
@@ -27,7 +54,90 @@ fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
     //    1.0
     //);
 
-    // Convert RGBA to BGRA and apply sRGB correction
-    return vec4f(pow(color.rgb, vec3f(2.2)), color.a);
+    // Tone map the unpremultiplied color, so edges against a transparent background match
+    var rgb = color.rgb;
+    if color.a > 0.0 {
+        rgb = grade(tone_map(rgb / color.a)) * color.a;
+    }
+
+    if display.encode_srgb != 0u {
+        rgb = srgb_encode(rgb);
+    }
+
+    return vec4f(rgb, color.a);
+}
+
+// Maps linear HDR color to linear display values in [0, 1]. Mirrors `ToneMapping::apply`
+fn tone_map(linear: vec3f) -> vec3f {
+    let color = linear * exp2(display.exposure);
+
+    var mapped: vec3f;
+    switch display.tone_mapper {
+        case 1u: { mapped = color / (color + 1.0); }
+        case 2u: { mapped = aces(color); }
+        case 3u: { mapped = agx(color); }
+        default: { mapped = color; }
+    }
+    return saturate(mapped);
+}
+
+// Applies the LUT to linear display values, which expects them display encoded. Mirrors
+// `Look::apply`
+fn grade(color: vec3f) -> vec3f {
+    if display.lut_strength <= 0.0 {
+        return color;
+    }
+
+    let encoded = srgb_encode(color);
+    let coords = saturate((encoded - display.lut_domain_min) /
+        (display.lut_domain_max - display.lut_domain_min));
+    // Sample texel centers, so the ends of the domain land on the first and last entries
+    let size = display.lut_size;
+    let graded = textureSampleLevel(lut, samp, (coords * (size - 1.0) + 0.5) / size, 0.0).rgb;
+
+    return srgb_decode(saturate(mix(encoded, graded, display.lut_strength)));
+}
+
+fn aces(x: vec3f) -> vec3f {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+// Minimal AgX, after https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx(color: vec3f) -> vec3f {
+    let inset = mat3x3f(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    let outset = mat3x3f(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let log = clamp(log2(inset * max(color, vec3f(1e-10))), vec3f(min_ev), vec3f(max_ev));
+    let x = (log - min_ev) / (max_ev - min_ev);
+
+    // Polynomial fit of the default contrast curve
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+
+    // The curve's output is display encoded, take it back to linear
+    return pow(max(outset * curve, vec3f(0.0)), vec3f(2.2));
+}
+
+fn srgb_encode(linear: vec3f) -> vec3f {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3f(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3f(0.0031308));
+}
 
+fn srgb_decode(encoded: vec3f) -> vec3f {
+    let low = encoded / 12.92;
+    let high = pow((encoded + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, encoded <= vec3f(0.04045));
 }
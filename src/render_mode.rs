@@ -0,0 +1,62 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// What `main_image` writes out for each pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    Shaded,
+    /// World space surface normals, mapped from [-1, 1] to [0, 1]
+    Normals,
+    /// Raymarching steps taken, as a fraction of the maximum
+    Steps,
+    /// Distance to the hit, white at the camera fading to black at the scene's depth range
+    Depth,
+    /// The distance estimator's orbit trap at the hit
+    OrbitTrap,
+    /// Distance estimator iterations before the orbit escaped
+    Iterations,
+    /// Green for hits, blue for escaped rays, red for rays that ran out of steps and yellow for
+    /// those that ran out right next to a surface
+    Classification,
+}
+
+impl RenderMode {
+    pub const ALL: [Self; 7] = [
+        Self::Shaded,
+        Self::Normals,
+        Self::Steps,
+        Self::Depth,
+        Self::OrbitTrap,
+        Self::Iterations,
+        Self::Classification,
+    ];
+
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Shaded => 0,
+            Self::Normals => 1,
+            Self::Steps => 2,
+            Self::Depth => 3,
+            Self::OrbitTrap => 4,
+            Self::Iterations => 5,
+            Self::Classification => 6,
+        }
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Shaded => "Shaded",
+            Self::Normals => "Normals",
+            Self::Steps => "Step count",
+            Self::Depth => "Depth",
+            Self::OrbitTrap => "Orbit trap",
+            Self::Iterations => "Iterations",
+            Self::Classification => "Ray classification",
+        };
+        write!(f, "{name}")
+    }
+}
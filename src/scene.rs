@@ -1,48 +1,379 @@
-use iced::widget::column;
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Clone)]
-pub enum Message {
-    Render,
-    ChangeWidth(String),
-    ChangeHeight(String),
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atmosphere::Atmosphere,
+    background::Background,
+    bookmark::Bookmark,
+    camera::{self, Camera},
+    environment::Environment,
+    fractal::{Fractal, FractalParameters},
+    light::Light,
+    lut::{self, Look},
+    marcher::Marcher,
+    material::Material,
+    post::PostEffect,
+    render_mode::RenderMode,
+    stereo::Stereo,
+    timeline::{self, Timeline},
+    tone_mapping::ToneMapping,
+};
+
+/// Keyword of the PNG text chunk rendered images carry their scene in.
+pub const PNG_SCENE_KEYWORD: &str = "fractals:scene";
+
+/// Name and version of the build writing scene files, for tracking down where one came from.
+const GENERATOR: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// Scene file version written by this build. Fields added since an older version take their
+/// defaults when it loads, bump this when an existing field changes meaning or older builds would
+/// ignore a new one and render something else.
+///
+/// 2 added the material and fractal parameters.
+const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    /// Written by a newer build, which may have changed what fields mean
+    UnsupportedVersion(u32),
+    Environment(image::ImageError),
+    Lut(lut::Error),
+    Png(png::DecodingError),
+    /// An image without a scene embedded by `offline::save`
+    NoEmbeddedScene,
+    Timeline(timeline::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Parse(error) => write!(f, "{error}"),
+            Self::Serialize(error) => write!(f, "{error}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "scene file version {version} is newer than the supported version {VERSION}"
+            ),
+            Self::Environment(error) => write!(f, "failed to load environment: {error}"),
+            Self::Lut(error) => write!(f, "failed to load LUT: {error}"),
+            Self::Png(error) => write!(f, "{error}"),
+            Self::NoEmbeddedScene => write!(f, "image has no scene embedded"),
+            Self::Timeline(error) => write!(f, "invalid timeline: {error}"),
+        }
+    }
 }
 
-pub enum Action {
-    Render,
-    ChangeImage,
-    None,
+impl std::error::Error for Error {}
+
+/// What's written to disk: the scene, tagged with the version it was written with.
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    version: u32,
+    /// Build that wrote the file
+    #[serde(default)]
+    generator: String,
+    scene: Scene,
+}
+
+/// Everything needed to reproduce an image. Environment maps and LUTs are saved by path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub fractal: Fractal,
+    pub fractal_parameters: FractalParameters,
+    pub material: Material,
+    pub camera: Camera,
+    /// Saved views to fly the camera between
+    pub bookmarks: Vec<Bookmark>,
+    pub environment: Environment,
+    pub background: Background,
+    pub light: Light,
+    pub atmosphere: Atmosphere,
+    pub marcher: Marcher,
+    pub render_mode: RenderMode,
+    /// Distance at which the depth debug view fades to black
+    pub depth_range: f32,
+    /// Frames to accumulate before the image is considered converged
+    pub samples: u32,
+    pub stereo: Stereo,
+    /// Frame rate the viewer lowers its resolution to keep up with while the view changes
+    pub target_frame_rate: f32,
+    /// Post processing, in the order it's applied
+    pub post: Vec<PostEffect>,
+    pub tone_mapping: ToneMapping,
+    pub look: Look,
+    pub timeline: Timeline,
+    /// Size of rendered images, fitting the projection where left out
+    pub width: Option<u32>,
+    pub height: Option<u32>,
 }
 
-pub struct Scene {}
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            fractal: Fractal::default(),
+            fractal_parameters: FractalParameters::default(),
+            material: Material::default(),
+            camera: Camera::default(),
+            bookmarks: Vec::new(),
+            environment: Environment::default(),
+            background: Background::default(),
+            light: Light::default(),
+            atmosphere: Atmosphere::default(),
+            marcher: Marcher::default(),
+            render_mode: RenderMode::default(),
+            depth_range: 10.0,
+            samples: 64,
+            stereo: Stereo::default(),
+            target_frame_rate: 30.0,
+            post: Vec::new(),
+            tone_mapping: ToneMapping::default(),
+            look: Look::default(),
+            timeline: Timeline::default(),
+            width: None,
+            height: None,
+        }
+    }
+}
 
 impl Scene {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Reads a scene file, or the scene embedded in a rendered PNG, loading the environment map
+    /// and LUT it refers to. Relative paths in the scene are relative to the file's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let is_png = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        if is_png {
+            return Self::parse_in(&embedded_scene(path)?, directory);
+        }
+
+        Self::parse_in(&fs::read_to_string(path).map_err(Error::Io)?, directory)
+    }
+
+    /// Reads a scene from the contents of a scene file, with paths relative to the working
+    /// directory.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        Self::parse_in(text, Path::new(""))
+    }
+
+    /// Reads a scene from the contents of a scene file in `directory`.
+    fn parse_in(text: &str, directory: &Path) -> Result<Self, Error> {
+        let file: SceneFile = ron::from_str(text).map_err(Error::Parse)?;
+        if file.version > VERSION {
+            return Err(Error::UnsupportedVersion(file.version));
+        }
+
+        let mut scene = file.scene;
+        scene.timeline.tidy().map_err(Error::Timeline)?;
+        if let Some(path) = &scene.environment.path {
+            let path = directory.join(path);
+            scene.environment.load(path).map_err(Error::Environment)?;
+        }
+        if let Some(path) = &scene.look.path {
+            let path = directory.join(path);
+            scene.look.load(path).map_err(Error::Lut)?;
+        }
+
+        Ok(scene)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or(Path::new(""));
+        fs::write(path, self.to_ron(directory)?).map_err(Error::Io)
+    }
+
+    /// The contents of a scene file for the scene, to be written to `directory`.
+    pub fn to_ron(&self, directory: &Path) -> Result<String, Error> {
+        let mut scene = self.clone();
+        for path in [&mut scene.environment.path, &mut scene.look.path]
+            .into_iter()
+            .flatten()
+        {
+            *path = relative_to(path, directory);
+        }
+
+        let file = SceneFile {
+            version: VERSION,
+            generator: GENERATOR.to_owned(),
+            scene,
+        };
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map_err(Error::Serialize)
+    }
+
+    /// Sets every keyframed parameter to its value `time` seconds into the timeline.
+    ///
+    /// The direction's components are keyed apart, so between keys facing opposite ways or past a
+    /// curve's overshoot they can cancel out or point straight up. The camera keeps facing the
+    /// way it was then, rather than losing its basis.
+    pub fn seek(&mut self, time: f32) {
+        let direction = self.camera.direction;
+        let values: Vec<_> = self
+            .timeline
+            .tracks
+            .iter()
+            .map(|track| (track.parameter, track.sample(time)))
+            .collect();
+        for (parameter, value) in values {
+            parameter.set(self, value);
+        }
+
+        self.camera.direction =
+            camera::usable_direction(self.camera.direction).unwrap_or(direction);
+    }
+
+    /// Tone mapping to show the render with. Debug views are already in [0, 1], so they're shown
+    /// as they are.
+    pub fn display_tone_mapping(&self) -> ToneMapping {
+        match self.render_mode {
+            RenderMode::Shaded => self.tone_mapping,
+            _ => ToneMapping::neutral(),
+        }
+    }
+
+    /// Grading to show the render with, left off for debug views like the tone mapping.
+    pub fn display_look(&self) -> Look {
+        match self.render_mode {
+            RenderMode::Shaded => self.look.clone(),
+            _ => Look::default(),
+        }
     }
 }
 
-impl Scene {
-    pub fn view(&self) -> iced::Element<'_, Message> {
-        column![].into()
-    }
-}
-
-// impl Image {
-//     #[must_use]
-//     pub fn update(&mut self, message: Message) -> Action {
-//         match message {
-//             Message::Render => {
-//                 // TODO: Render out a new image
-//                 Action::None
-//             }
-//             Message::ChangeWidth(width) => {
-//                 self.image_width = width.parse().unwrap_or(self.image_width);
-//                 Action::None
-//             }
-//             Message::ChangeHeight(height) => {
-//                 self.image_height = height.parse().unwrap_or(self.image_height);
-//                 Action::None
-//             }
-//         }
-//     }
-// }
+/// A path relative to the working directory, made relative to a scene file in `directory`
+/// instead. Paths outside of it are made absolute, so the scene still finds them.
+fn relative_to(path: &Path, directory: &Path) -> PathBuf {
+    if path.is_relative() {
+        if let Ok(relative) = path.strip_prefix(directory) {
+            return relative.to_owned();
+        }
+    }
+
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    let (Ok(path), Ok(directory)) = (fs::canonicalize(path), fs::canonicalize(directory)) else {
+        return path.to_owned();
+    };
+    match path.strip_prefix(&directory) {
+        Ok(relative) => relative.to_owned(),
+        Err(_) => path,
+    }
+}
+
+/// Text of the scene chunk in a PNG written by `offline::save`.
+fn embedded_scene(path: &Path) -> Result<String, Error> {
+    let file = BufReader::new(File::open(path).map_err(Error::Io)?);
+    let reader = png::Decoder::new(file).read_info().map_err(Error::Png)?;
+
+    let info = reader.info();
+    let chunk = info
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == PNG_SCENE_KEYWORD)
+        .ok_or(Error::NoEmbeddedScene)?;
+    chunk.get_text().map_err(Error::Png)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::timeline::Parameter;
+
+    #[test]
+    fn round_trips_through_ron() {
+        let mut scene = Scene::new();
+        scene.fractal = Fractal::Mandelbox;
+        scene.fractal_parameters.mandelbox.scale = -1.5;
+        scene.material.shininess = 32.0;
+        scene.light.position = Vec3::new(1.0, 2.0, 3.0);
+        scene.camera.fov = 35.0;
+        scene.width = Some(640);
+
+        let text = scene.to_ron(Path::new("")).unwrap();
+        let loaded = Scene::parse(&text).unwrap();
+
+        assert_eq!(loaded.fractal, Fractal::Mandelbox);
+        assert_eq!(loaded.fractal_parameters, scene.fractal_parameters);
+        assert_eq!(loaded.material, scene.material);
+        assert_eq!(loaded.light, scene.light);
+        assert_eq!(loaded.camera.fov, 35.0);
+        assert_eq!(loaded.width, Some(640));
+        assert_eq!(loaded.to_ron(Path::new("")).unwrap(), text);
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        let scene = Scene::parse("(version: 1, scene: (fractal: Sierpinski))").unwrap();
+
+        assert_eq!(scene.fractal, Fractal::Sierpinski);
+        assert_eq!(scene.material, Material::default());
+        assert_eq!(scene.samples, Scene::default().samples);
+    }
+
+    #[test]
+    fn seeks_to_usable_directions() {
+        let mut scene = Scene::new();
+        scene.camera.direction = Vec3::Y;
+        for (time, x) in [(0.0, 2.0), (1.0, -2.0)] {
+            scene.timeline.set_key(Parameter::DirectionX, time, x);
+            scene.timeline.set_key(Parameter::DirectionY, time, 0.0);
+            scene.timeline.set_key(Parameter::DirectionZ, time, 0.0);
+        }
+
+        scene.seek(0.0);
+        assert_eq!(scene.camera.direction, Vec3::X);
+
+        // Halfway the components cancel out, so the camera keeps facing the way it was
+        scene.seek(0.5);
+        assert_eq!(scene.camera.direction, Vec3::X);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let text = format!("(version: {}, scene: ())", VERSION + 1);
+
+        assert!(matches!(
+            Scene::parse(&text),
+            Err(Error::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn makes_paths_relative_to_the_scene_file() {
+        let directory = Path::new("scenes");
+
+        assert_eq!(
+            relative_to(Path::new("scenes/sky.hdr"), directory),
+            Path::new("sky.hdr")
+        );
+        assert_eq!(
+            relative_to(Path::new("/maps/sky.hdr"), directory),
+            Path::new("/maps/sky.hdr")
+        );
+        assert_eq!(
+            relative_to(Path::new("sky.hdr"), Path::new("")),
+            Path::new("sky.hdr")
+        );
+    }
+}
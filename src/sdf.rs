@@ -0,0 +1,142 @@
+// CPU-side mirrors of the distance estimators in `shader.wgsl`.
+//
+// These need to stay in sync with the shader so that anything we raymarch on the CPU (picking,
+// focus, etc.) agrees with what ends up on screen.
+
+use glam::{Vec3, Vec4};
+
+use crate::{
+    camera::PixelSize,
+    fractal::{Fractal, FractalParameters, MandelboxParameters, SierpinskiParameters},
+    marcher::Marcher,
+};
+
+pub fn sphere_sdf(point: Vec3) -> f32 {
+    let x = point.x.signum() * (point.x % 1.0);
+    let y = point.y.signum() * (point.y % 1.0);
+
+    let instance = Vec3::new(x, y, point.z) - Vec3::splat(0.5);
+
+    instance.length() - 0.15
+}
+
+pub fn sierpinsky_sdf(point: Vec3, parameters: &SierpinskiParameters) -> f32 {
+    let max_iterations = parameters.iterations as i32;
+    let scale = parameters.scale;
+
+    let mut p = point;
+
+    let a1 = Vec3::new(1.0, 1.0, 1.0);
+    let a2 = Vec3::new(-1.0, -1.0, 1.0);
+    let a3 = Vec3::new(1.0, -1.0, -1.0);
+    let a4 = Vec3::new(-1.0, 1.0, -1.0);
+
+    for _ in 0..max_iterations {
+        let mut c = a1;
+        let mut dist = (p - a1).length();
+
+        for a in [a2, a3, a4] {
+            let d = (p - a).length();
+            if d < dist {
+                c = a;
+                dist = d;
+            }
+        }
+
+        p = scale * p - c * (scale - 1.0);
+    }
+
+    p.length() * f32::powi(scale, -max_iterations)
+}
+
+fn box_fold(point: Vec3, fold_limit: f32) -> Vec3 {
+    (2.0 * point.clamp(Vec3::splat(-fold_limit), Vec3::splat(fold_limit))) - point
+}
+
+fn sphere_fold(point: Vec3, dr: f32, min_radius: f32, max_radius: f32) -> Vec4 {
+    let radius = point.length();
+
+    if radius < min_radius {
+        let ratio = max_radius / min_radius;
+        (point * ratio).extend(ratio)
+    } else if radius < max_radius {
+        let ratio = max_radius / radius;
+        (point * ratio).extend(ratio)
+    } else {
+        point.extend(dr)
+    }
+}
+
+pub fn mandelbox_sdf(point: Vec3, parameters: &MandelboxParameters) -> f32 {
+    let scale = parameters.scale;
+
+    let mut p = point;
+    let mut dr: f32 = 1.0;
+
+    for _ in 0..parameters.iterations {
+        p = box_fold(p, parameters.fold_limit);
+
+        let fold = sphere_fold(p, dr, parameters.min_radius, parameters.max_radius);
+        p = fold.truncate();
+        dr = fold.w;
+
+        p = (scale * p) + point;
+        dr = dr * f32::abs(scale) + 1.0;
+    }
+
+    p.length() / dr.abs()
+}
+
+pub fn sdf(fractal: Fractal, parameters: &FractalParameters, point: Vec3) -> f32 {
+    match fractal {
+        Fractal::SphereLattice => sphere_sdf(point),
+        Fractal::Sierpinski => sierpinsky_sdf(point, &parameters.sierpinski),
+        Fractal::Mandelbox => mandelbox_sdf(point, &parameters.mandelbox),
+    }
+}
+
+/// Sphere traces a single ray, returning the distance to the surface if it hits one. Over-relaxed
+/// the same way as `march` in `shader.wgsl`, so picks and collisions land where the render shows
+/// the surface.
+pub fn raymarch(
+    fractal: Fractal,
+    parameters: &FractalParameters,
+    origin: Vec3,
+    direction: Vec3,
+    marcher: &Marcher,
+    pixel: PixelSize,
+) -> Option<f32> {
+    let mut total_distance = 0.0;
+    let mut omega = marcher.relaxation;
+    let mut previous_radius = 0.0;
+    let mut step_length = 0.0;
+
+    for _ in 0..marcher.max_steps {
+        let radius = sdf(fractal, parameters, origin + total_distance * direction);
+
+        let overstepped = omega > 1.0 && (radius + previous_radius) < step_length;
+        if overstepped {
+            // Undo the relaxed part of the last step and carry on conservatively
+            step_length -= omega * step_length;
+            omega = 1.0;
+        } else {
+            step_length = radius * omega;
+        }
+        previous_radius = radius;
+
+        let epsilon = f32::max(
+            marcher.min_distance,
+            marcher.pixel_epsilon * pixel.at(total_distance),
+        );
+        if !overstepped && radius < epsilon {
+            return Some(total_distance);
+        }
+        if total_distance > marcher.max_distance || radius > marcher.max_distance {
+            return None;
+        }
+
+        total_distance += step_length;
+    }
+
+    None
+}
@@ -1,75 +1,569 @@
-@group(0) @binding(0) var screen: texture_storage_2d<rgba8unorm,write>;
+struct Uniforms {
+    camera_position: vec3f,
+    environment_intensity: f32,
+    camera_direction: vec3f,
+    environment_rotation: f32,
+    background_color: vec3f,
+    environment_enabled: u32,
+    background_secondary_color: vec3f,
+    background_mode: u32,
+    sun_direction: vec3f,
+    sun_intensity: f32,
+    environment_levels: f32,
+    fog_density: f32,
+    fog_height: f32,
+    fog_height_falloff: f32,
+    fog_color: vec3f,
+    glow_intensity: f32,
+    glow_color: vec3f,
+    scattering: f32,
+    max_steps: u32,
+    max_distance: f32,
+    min_distance: f32,
+    normal_sampling_distance: f32,
+    pixel_epsilon: f32,
+    relaxation: f32,
+    normal_estimator: u32,
+    render_mode: u32,
+    depth_range: f32,
+    aperture: f32,
+    focus_distance: f32,
+    // Index of the sample being accumulated, zero to start over
+    frame: u32,
+    // Radians, along the axis given by `fov_axis`
+    fov: f32,
+    // 0 for vertical, 1 for horizontal
+    fov_axis: u32,
+    projection: u32,
+    // Height of the view for orthographic projection, in world units
+    orthographic_size: f32,
+    panini_distance: f32,
+    // 0 for mono, then side by side, over under and anaglyph
+    stereo_mode: u32,
+    interocular_distance: f32,
+    convergence_distance: f32,
+    // Camera pose the history was rendered from
+    previous_camera_position: vec3f,
+    // Changes every dispatch, unlike `frame`
+    seed: u32,
+    previous_camera_direction: vec3f,
+    // 1 to blend with the reprojected history rather than accumulate, while the camera moves
+    temporal: u32,
+    // Pixels rendered this frame and last, in the top left corner of the screen texture. Less
+    // than its full size while the viewer lowers the resolution to keep up
+    render_size: vec2u,
+    previous_render_size: vec2u,
+    light_position: vec3f,
+    // 0 for the sphere lattice, then Sierpinski and Mandelbox
+    fractal: u32,
+    light_color: vec3f,
+    // 1 to sum surface attributes into `aovs` for compositing, alongside the image
+    aovs: u32,
+    diffuse_color: vec3f,
+    shininess: f32,
+    specular_color: vec3f,
+    sierpinski_iterations: u32,
+    sierpinski_scale: f32,
+    mandelbox_iterations: u32,
+    mandelbox_scale: f32,
+    mandelbox_fold_limit: f32,
+    mandelbox_min_radius: f32,
+    mandelbox_max_radius: f32,
+}
+
+// A traced pixel, with the distance to the nearest hit or -1 for a miss. The color is packed to
+// half floats to keep the buffers the same size as `accumulation`
+struct Sample {
+    color: vec2u,
+    depth: f32,
+}
+
+fn pack_sample(color: vec4f, depth: f32) -> Sample {
+    return Sample(vec2u(pack2x16float(color.rg), pack2x16float(color.ba)), depth);
+}
+
+fn sample_color(sample: Sample) -> vec4f {
+    return vec4f(unpack2x16float(sample.color.x), unpack2x16float(sample.color.y));
+}
+
+// Sums of a pixel's surface attributes over every sample that hit the fractal
+struct Aov {
+    // World space hit point, and depth along the view direction
+    position_depth: vec4f,
+    // World space normal, and ambient occlusion from 0 for fully occluded to 1 for open
+    normal_occlusion: vec4f,
+    // Orbit trap and distance estimator iterations, then the samples that hit and the samples
+    // taken, hit or not
+    trap_iterations_coverage: vec4f,
+}
+
+@group(0) @binding(0) var screen: texture_storage_2d<rgba16float,write>;
+// Equirectangular environment map, with a full mip chain
 @group(0) @binding(1) var channel0: texture_2d<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+@group(0) @binding(3) var<uniform> uniforms: Uniforms;
+// Running sum of every sample since the last reset, one per pixel
+@group(0) @binding(4) var<storage, read_write> accumulation: array<vec4f>;
+// This frame's samples, written by `main_image` and resolved by `resolve`
+@group(0) @binding(5) var<storage, read_write> current: array<Sample>;
+// The previous frame's resolved image, and where this frame's goes. Swapped every dispatch
+@group(0) @binding(6) var<storage, read> history: array<Sample>;
+@group(0) @binding(7) var<storage, read_write> next_history: array<Sample>;
+// Surface attributes summed since the last reset, while `uniforms.aovs` is set
+@group(0) @binding(8) var<storage, read_write> aovs: array<Aov>;
+
+const PI = 3.14159265359;
+const TAU = 6.28318530718;
 
 // Camera params
-// TODO: Feed in through a uniform buffer
-const camera_position = vec3f(-4, 1, 1);
-const camera_direction = vec3f(1, 0, -0.3);
 const jitter_strength = 0.00005;
 
 // Light params
-// TODO: Feed in through a uniform buffer
-const light_position = vec3f(-4, 0, 5);
-const light_color = vec3f(1.0);
 const diffuse_power = 20.0;
 const specular_power = 10.0;
 
 const ambient_color = vec3f(0.1);
 
-// Material params
-// TODO: Feed in through a uniform buffer
-const diffuse_color = vec3f(0.5);
-const specular_color = vec3f(1.0);
-const shininess = 1.0;
+// Share of each new frame in the temporal blend. Lower is smoother but ghosts more
+const temporal_blend = 0.1;
+
+// Width of a pixel one unit away from the camera, for scaling the hit epsilon with distance
+var<private> pixel_footprint: f32;
+// Width of a pixel regardless of distance, for orthographic projection
+var<private> pixel_width: f32;
+
+// Written by the distance estimators as they run, for the debug views. Only meaningful right
+// after a call to `sdf`
+var<private> orbit_trap: f32;
+var<private> iteration_count: i32;
+var<private> iteration_limit: i32;
+
+// Distance to the surface hit by the last call to `trace`, or -1 for a miss
+var<private> hit_distance: f32;
+// Where that surface was hit
+var<private> hit_point: vec3f;
+
+var<private> rng_state: u32;
+
+// PCG hash. See https://www.jcgt.org/published/0009/03/02/
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1)
+fn random() -> f32 {
+    rng_state = pcg(rng_state);
+    return f32(rng_state) / 4294967296.0;
+}
+
+// Uniform on the unit disk
+fn random_disk() -> vec2f {
+    let radius = sqrt(random());
+    let angle = TAU * random();
+    return radius * vec2f(cos(angle), sin(angle));
+}
+
+struct Ray {
+    origin: vec3f,
+    // Zero for points outside the projection, e.g. past the edge of a fisheye
+    direction: vec3f,
+}
+
+struct CameraBasis {
+    forward: vec3f,
+    right: vec3f,
+    up: vec3f,
+}
+
+fn camera_basis() -> CameraBasis {
+    let forward = normalize(uniforms.camera_direction);
+    let right = normalize(cross(forward, vec3f(0, 0, 1)));
+    let up = cross(right, forward);
+    return CameraBasis(forward, right, up);
+}
+
+// Camera heading with world up, for panoramas
+fn level_basis() -> CameraBasis {
+    var heading = uniforms.camera_direction;
+    heading.z = 0.0;
+    if dot(heading, heading) == 0.0 {
+        heading = vec3f(1, 0, 0);
+    }
+    let forward = normalize(heading);
+    let right = cross(forward, vec3f(0, 0, 1));
+    return CameraBasis(forward, right, vec3f(0, 0, 1));
+}
+
+// Tangent of half the vertical field of view
+fn vertical_half_tangent(aspect_ratio: f32) -> f32 {
+    let half_tangent = tan(uniforms.fov * 0.5);
+    if uniforms.fov_axis == 1u {
+        return half_tangent / aspect_ratio;
+    }
+    return half_tangent;
+}
+
+// Pinhole ray through `uv`, from -0.5 to 0.5 with y up. Mirrored by `Camera::ray` on the CPU
+fn camera_ray(uv: vec2f, aspect_ratio: f32) -> Ray {
+    let basis = camera_basis();
+    let position = uniforms.camera_position;
+
+    // Image plane coordinates, one unit in front of the camera for perspective projection
+    let plane = vec2f(uv.x * aspect_ratio, uv.y) * 2.0 * vertical_half_tangent(aspect_ratio);
+
+    switch uniforms.projection {
+        case 1u: {
+            // Orthographic
+            let offset = vec2f(uv.x * aspect_ratio, uv.y) * uniforms.orthographic_size;
+            return Ray(position + offset.x * basis.right + offset.y * basis.up, basis.forward);
+        }
+        case 2u: {
+            // Equidistant fisheye, angle from the centre proportional to distance on screen
+            var vertical_fov = uniforms.fov;
+            if uniforms.fov_axis == 1u {
+                vertical_fov /= aspect_ratio;
+            }
+            let angles = vec2f(uv.x * aspect_ratio, uv.y) * vertical_fov;
+            let theta = length(angles);
+            if theta > PI {
+                return Ray(position, vec3f(0.0));
+            }
+            var side = vec2f(0.0);
+            if theta > 0.0 {
+                side = angles / theta * sin(theta);
+            }
+            return Ray(position, basis.forward * cos(theta) + side.x * basis.right + side.y * basis.up);
+        }
+        case 3u: {
+            // Panini, keeps verticals and radial lines through the centre straight. See Sharpless
+            // et al., "Pannini: A New Projection for Rendering Wide Angle Perspective Images"
+            let d = uniforms.panini_distance;
+            // A horizontal field of view needs measuring through the projection itself, plain
+            // perspective would push the edges past where Panini can reach
+            var panini = plane;
+            if uniforms.fov_axis == 1u {
+                let h = uniforms.fov * 0.5;
+                let edge = (d + 1.0) * sin(h) / (d + cos(h));
+                panini = vec2f(uv.x * aspect_ratio, uv.y) * 2.0 * edge / aspect_ratio;
+            }
+            let k = panini.x * panini.x / ((d + 1.0) * (d + 1.0));
+            let discriminant = k * k * d * d - (k + 1.0) * (k * d * d - 1.0);
+            let cos_longitude = (-k * d + sqrt(discriminant)) / (k + 1.0);
+            let s = (d + 1.0) / (d + cos_longitude);
+            let longitude = atan2(panini.x, s * cos_longitude);
+            let latitude = atan2(panini.y, s);
+            let local = vec3f(sin(longitude) * cos(latitude), sin(latitude), cos(longitude) * cos(latitude));
+            return Ray(position, local.x * basis.right + local.y * basis.up + local.z * basis.forward);
+        }
+        case 4u: {
+            // Equirectangular panorama, longitude across and latitude up, centred on the view
+            // direction. Kept level with the world so the horizon runs straight across
+            let level = level_basis();
+            let longitude = uv.x * TAU;
+            let latitude = uv.y * PI;
+            let horizontal = cos(longitude) * level.forward + sin(longitude) * level.right;
+            return Ray(position, cos(latitude) * horizontal + sin(latitude) * level.up);
+        }
+        case 5u: {
+            // Cube map faces in a 3x2 grid. Top row: front, right, back. Bottom row: left, up, down
+            let level = level_basis();
+            let grid = (uv + 0.5) * vec2f(3.0, 2.0);
+            let cell = min(vec2u(grid), vec2u(2u, 1u));
+            let local = fract(grid) * 2.0 - 1.0;
+
+            var face = level;
+            switch cell.x + (1u - cell.y) * 3u {
+                case 1u: { face = CameraBasis(level.right, -level.forward, level.up); }
+                case 2u: { face = CameraBasis(-level.forward, -level.right, level.up); }
+                case 3u: { face = CameraBasis(-level.right, level.forward, level.up); }
+                case 4u: { face = CameraBasis(level.up, level.right, -level.forward); }
+                case 5u: { face = CameraBasis(-level.up, level.right, level.forward); }
+                default: {}
+            }
+            return Ray(position, normalize(face.forward + local.x * face.right + local.y * face.up));
+        }
+        default: {
+            return Ray(position, normalize(basis.forward + plane.x * basis.right + plane.y * basis.up));
+        }
+    }
+}
+
+// Sets the pixel size globals for a view of `size` pixels
+fn set_pixel_size(size: vec2f) {
+    let aspect_ratio = size.x / size.y;
+
+    switch uniforms.projection {
+        case 1u: {
+            pixel_footprint = 0.0;
+            pixel_width = uniforms.orthographic_size / size.y;
+        }
+        case 4u: {
+            pixel_footprint = PI / size.y;
+            pixel_width = 0.0;
+        }
+        case 5u: {
+            pixel_footprint = 4.0 / size.y;
+            pixel_width = 0.0;
+        }
+        default: {
+            pixel_footprint = 2.0 * vertical_half_tangent(aspect_ratio) / size.y;
+            pixel_width = 0.0;
+        }
+    }
+}
+
+// Traces one sample through `uv` of a view with the given aspect ratio. `eye` is -1 for the left
+// eye, 1 for the right and 0 without stereo
+fn sample_view(uv: vec2f, aspect_ratio: f32, eye: f32) -> vec4f {
+    let basis = camera_basis();
+
+    let pinhole = camera_ray(uv, aspect_ratio);
+    if all(pinhole.direction == vec3f(0.0)) {
+        hit_distance = -1.0;
+        return vec4f(0.0);
+    }
+
+    // Projections wider than a plane can cover converge and focus on spheres instead
+    var plane_scale = 1.0;
+    if uniforms.projection <= 1u {
+        plane_scale = 1.0 / dot(pinhole.direction, basis.forward);
+    }
+
+    // Off-axis stereo: each eye is shifted sideways but still looks through the same points on
+    // the convergence plane, so objects there have zero parallax
+    let convergence_point = pinhole.origin + pinhole.direction * uniforms.convergence_distance * plane_scale;
+    let eye_origin = pinhole.origin + basis.right * eye * uniforms.interocular_distance * 0.5;
+    let eye_direction = normalize(convergence_point - eye_origin);
 
-// Screen params
-// TODO: Feed in through a uniform buffer
-const gamma = 2.2; // sRGB
+    // Thin lens: everything on the focal plane stays sharp, wherever on the aperture the ray
+    // starts from
+    let focal_point = eye_origin + eye_direction * uniforms.focus_distance * plane_scale;
+    let lens = uniforms.aperture * random_disk();
+    let ray_origin = eye_origin + lens.x * basis.right + lens.y * basis.up;
+    let ray_direction = normalize(focal_point - ray_origin);
+
+    return trace(ray_origin, ray_direction);
+}
 
 @compute @workgroup_size(8, 8, 1)
 fn main_image(@builtin(global_invocation_id) id: vec3u) {
     // Viewport resolution (in pixels)
-    let screen_size = textureDimensions(screen);
+    let screen_size = uniforms.render_size;
 
     // Prevent overdraw for workgroups on the edge of the viewport
     if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
 
-    // Get camera basis vectors
-    let cam_x = cross(normalize(camera_direction), vec3f(0,0,1));
-    let cam_y = cross(cam_x, normalize(camera_direction));
+    // Pixel coordinates (bottom left corner of pixel, origin at bottom left)
+    let pixel = vec2f(f32(id.x), f32(screen_size.y - id.y) - 1.0);
+
+    // Split the screen into a view per eye where needed
+    var view_size = vec2f(screen_size);
+    var view_origin = vec2f(0.0);
+    var eye = 0.0;
+    switch uniforms.stereo_mode {
+        case 1u: {
+            // Side by side, left eye on the left
+            view_size.x *= 0.5;
+            eye = select(1.0, -1.0, pixel.x < view_size.x);
+            view_origin.x = select(view_size.x, 0.0, pixel.x < view_size.x);
+        }
+        case 2u: {
+            // Over under, left eye on top
+            view_size.y *= 0.5;
+            eye = select(1.0, -1.0, pixel.y >= view_size.y);
+            view_origin.y = select(0.0, view_size.y, pixel.y >= view_size.y);
+        }
+        default: {}
+    }
+
+    let aspect_ratio = view_size.x / view_size.y;
+    set_pixel_size(view_size);
 
-    let aspect_ratio = f32(screen_size.x) / f32(screen_size.y);
+    rng_state = pcg(id.x + pcg(id.y + pcg(uniforms.seed)));
 
-    // Super sampling
-    let subsamples = 2;
+    // Super sampling, down to a single sample while the temporal blend smooths edges instead
+    let subsamples = select(2, 1, uniforms.temporal != 0u);
     var color_acc = vec4f(0.0);
+    var depth = -1.0;
+    var aov = Aov(vec4f(0.0), vec4f(0.0), vec4f(0.0));
     for (var i = 0; i < subsamples; i++) {
         for (var j = 0; j < subsamples; j++) {
-            // Pixel coordinates (centre of pixel, origin at bottom left)
-            var fragCoord = vec2f(f32(id.x) + .5, f32(screen_size.y - id.y) - .5);
-
-            // Subpixel offset
-            let offset = vec2f(f32(i), f32(j)) / f32(subsamples);
-            fragCoord += offset;
+            // Subpixel offset, jittered within each subsample so accumulated frames converge
+            let offset = (vec2f(f32(i), f32(j)) + vec2f(random(), random())) / f32(subsamples);
+            let fragCoord = pixel + offset - view_origin;
 
             // Normalised pixel coordinates (from -0.5 to 0.5)
-            var uv = fragCoord / vec2f(screen_size) - 0.5;
+            var uv = fragCoord / view_size - 0.5;
 
             // Ray jitter
             // uv += jitter(fragCoord);
 
-            let ray_direction = normalize(camera_direction +
-                                        (uv.x * cam_x * aspect_ratio) +
-                                        (uv.y * cam_y));
+            if uniforms.stereo_mode == 3u {
+                // Red/cyan anaglyph, red from the left eye and the rest from the right
+                let left = sample_view(uv, aspect_ratio, -1.0);
+                let right = sample_view(uv, aspect_ratio, 1.0);
+                color_acc += vec4f(left.r, right.g, right.b, max(left.a, right.a));
+            } else {
+                color_acc += sample_view(uv, aspect_ratio, eye);
+            }
+
+            // Keep the nearest hit, for reprojection
+            if hit_distance >= 0.0 && (depth < 0.0 || hit_distance < depth) {
+                depth = hit_distance;
+            }
 
-            color_acc += trace(camera_position, ray_direction);
+            if uniforms.aovs != 0u {
+                if hit_distance >= 0.0 {
+                    let surface = surface_aov(hit_point, hit_distance);
+                    aov.position_depth += surface.position_depth;
+                    aov.normal_occlusion += surface.normal_occlusion;
+                    aov.trap_iterations_coverage += surface.trap_iterations_coverage;
+                }
+                aov.trap_iterations_coverage.w += 1.0;
+            }
         }
     }
 
     // Normalise extra samples
     color_acc /= f32(subsamples * subsamples);
+
+    // AOVs are never reprojected, so they accumulate here rather than in `resolve`
+    if uniforms.aovs != 0u {
+        let index = id.y * textureDimensions(screen).x + id.x;
+        if uniforms.frame != 0u {
+            let previous = aovs[index];
+            aov.position_depth += previous.position_depth;
+            aov.normal_occlusion += previous.normal_occlusion;
+            aov.trap_iterations_coverage += previous.trap_iterations_coverage;
+        }
+        aovs[index] = aov;
+    }
+
+    current[id.y * textureDimensions(screen).x + id.x] = pack_sample(color_acc, depth);
+}
+
+// Where `point` appeared in the previous frame, in pixels from the top left. Only planar
+// projections are reprojected
+fn reproject(point: vec3f, size: vec2f) -> vec2f {
+    let forward = normalize(uniforms.previous_camera_direction);
+    let right = normalize(cross(forward, vec3f(0, 0, 1)));
+    let up = cross(right, forward);
+
+    let offset = point - uniforms.previous_camera_position;
+    let local = vec3f(dot(offset, right), dot(offset, up), dot(offset, forward));
+
+    var screen: vec2f;
+    if uniforms.projection == 1u {
+        screen = local.xy / uniforms.orthographic_size;
+    } else {
+        screen = local.xy / (local.z * 2.0 * vertical_half_tangent(size.x / size.y));
+    }
+
+    let uv = vec2f(screen.x * size.y / size.x, screen.y) + 0.5;
+    return vec2f(uv.x, 1.0 - uv.y) * size;
+}
+
+fn history_at(pixel: vec2i, size: vec2i) -> Sample {
+    let clamped = clamp(pixel, vec2i(0), size - 1);
+    return history[u32(clamped.y) * textureDimensions(screen).x + u32(clamped.x)];
+}
+
+// Bilinearly filtered history color at `position`, in pixels from the top left
+fn sample_history(position: vec2f, size: vec2i) -> vec4f {
+    let texel = position - 0.5;
+    let base = vec2i(floor(texel));
+    let t = fract(texel);
+
+    let top = mix(
+        sample_color(history_at(base, size)),
+        sample_color(history_at(base + vec2i(1, 0), size)),
+        t.x
+    );
+    let bottom = mix(
+        sample_color(history_at(base + vec2i(0, 1), size)),
+        sample_color(history_at(base + vec2i(1, 1), size)),
+        t.x
+    );
+    return mix(top, bottom, t.y);
+}
+
+// Blends this frame's samples into the accumulated image, or with the reprojected history while
+// the camera moves
+@compute @workgroup_size(8, 8, 1)
+fn resolve(@builtin(global_invocation_id) id: vec3u) {
+    let screen_size = uniforms.render_size;
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Buffers are laid out for the whole screen texture, whatever the render size
+    let stride = textureDimensions(screen).x;
+    let index = id.y * stride + id.x;
+    let sample = current[index];
+    let traced = sample_color(sample);
+    var color = traced;
+
+    if uniforms.temporal != 0u {
+        let size = vec2i(screen_size);
+        let view_size = vec2f(screen_size);
+
+        // Find the pixel's surface, or a far away point along its ray for a miss
+        let uv = (vec2f(f32(id.x), f32(screen_size.y - id.y) - 1.0) + 0.5) / view_size - 0.5;
+        let ray = camera_ray(uv, view_size.x / view_size.y);
+        let distance = select(uniforms.max_distance, sample.depth, sample.depth >= 0.0);
+        let point = ray.origin + ray.direction * distance;
+        let previous_size = vec2f(uniforms.previous_render_size);
+        let previous = reproject(point, previous_size);
+
+        // Disocclusions show something in the history that is nearer than this surface
+        let inside = all(previous >= vec2f(0.0)) && all(previous < previous_size);
+        let previous_depth = history_at(vec2i(previous), vec2i(previous_size)).depth;
+        let offset = point - uniforms.previous_camera_position;
+        var expected_depth = length(offset);
+        if uniforms.projection == 1u {
+            expected_depth = dot(offset, normalize(uniforms.previous_camera_direction));
+        }
+        let occluded = sample.depth >= 0.0
+            && (previous_depth < 0.0 || previous_depth < expected_depth * 0.9);
+
+        if inside && !occluded {
+            // Clamp the history to the colors around this pixel, which rejects most of what is
+            // left of stale or mismatched history
+            var low = traced;
+            var high = traced;
+            for (var y = -1; y <= 1; y++) {
+                for (var x = -1; x <= 1; x++) {
+                    let neighbour = clamp(vec2i(id.xy) + vec2i(x, y), vec2i(0), size - 1);
+                    let neighbour_index = u32(neighbour.y) * stride + u32(neighbour.x);
+                    let neighbour_color = sample_color(current[neighbour_index]);
+                    low = min(low, neighbour_color);
+                    high = max(high, neighbour_color);
+                }
+            }
+
+            let history_color = clamp(
+                sample_history(previous, vec2i(previous_size)),
+                low,
+                high
+            );
+            color = mix(history_color, traced, temporal_blend);
+        }
+
+        // The blended result stands in for the first accumulated sample once the camera stops
+        accumulation[index] = color;
+    } else {
+        // Accumulate with previous frames
+        if uniforms.frame != 0u {
+            color += accumulation[index];
+        }
+        accumulation[index] = color;
+        color /= f32(uniforms.frame + 1u);
+    }
+
+    next_history[index] = pack_sample(color, sample.depth);
+
     // Output to screen
-    textureStore(screen, id.xy, color_acc);
+    textureStore(screen, id.xy, color);
 }
 
 // fn jitter(fragCoord: vec2f) -> vec2f {
@@ -81,19 +575,99 @@ fn main_image(@builtin(global_invocation_id) id: vec3u) {
 //     return jitter_strength * (noise.xy - 0.5);
 // }
 
+fn environment_uv(direction: vec3f) -> vec2f {
+    let d = normalize(direction);
+    let phi = atan2(d.y, d.x) + uniforms.environment_rotation;
+    let theta = acos(clamp(d.z, -1.0, 1.0));
+    return vec2f(fract(phi / TAU + 0.5), theta / PI);
+}
+
+fn sample_environment(direction: vec3f, lod: f32) -> vec3f {
+    let color = textureSampleLevel(channel0, environment_sampler, environment_uv(direction), lod);
+    return color.rgb * uniforms.environment_intensity;
+}
+
+// Approximates the irradiance arriving from the hemisphere around the normal by sampling one of
+// the coarsest mip levels
+fn environment_irradiance(normal: vec3f) -> vec3f {
+    let lod = max(uniforms.environment_levels - 4.0, 0.0);
+    return sample_environment(normal, lod);
+}
+
+// Glossy reflection, blurrier for lower shininess
+fn environment_radiance(reflected: vec3f, shininess: f32) -> vec3f {
+    let max_lod = uniforms.environment_levels - 1.0;
+    let roughness = 1.0 - clamp(log2(shininess) / 10.0, 0.0, 1.0);
+    return sample_environment(reflected, roughness * max_lod);
+}
+
+// Cheap analytic daylight: a horizon to zenith blend that reddens as the sun sets, a forward
+// scattering halo and the sun disk itself
+fn sky(direction: vec3f) -> vec3f {
+    let d = normalize(direction);
+    let sun = uniforms.sun_direction;
+    let daylight = clamp(sun.z * 2.0 + 0.2, 0.0, 1.0);
+
+    let zenith = mix(vec3f(0.02, 0.03, 0.08), vec3f(0.15, 0.35, 0.8), daylight);
+    let horizon = mix(vec3f(0.5, 0.25, 0.1), vec3f(0.7, 0.8, 0.95), daylight);
+    let ground = horizon * 0.3;
+
+    var color = mix(horizon, zenith, sqrt(max(d.z, 0.0)));
+    if d.z < 0.0 {
+        color = mix(horizon, ground, sqrt(-d.z));
+    }
+
+    let mu = max(dot(d, sun), 0.0);
+    color += horizon * pow(mu, 8.0) * 0.5;
+
+    let sun_radius = 0.00935; // Angular radius of the real sun, in radians
+    color += vec3f(1.0, 0.95, 0.85) * uniforms.sun_intensity * smoothstep(cos(sun_radius * 1.5), cos(sun_radius), mu);
+
+    return color;
+}
+
+// Colour of rays that don't hit anything
+fn background(direction: vec3f) -> vec4f {
+    switch uniforms.background_mode {
+        case 0u: {
+            return vec4f(uniforms.background_color, 1.0);
+        }
+        case 1u: {
+            let t = normalize(direction).z * 0.5 + 0.5;
+            return vec4f(mix(uniforms.background_secondary_color, uniforms.background_color, t), 1.0);
+        }
+        case 2u: {
+            return vec4f(sky(direction), 1.0);
+        }
+        case 3u: {
+            if uniforms.environment_enabled == 0u {
+                return vec4f(0.0, 0.0, 0.0, 1.0);
+            }
+            return vec4f(sample_environment(direction, 0.0), 1.0);
+        }
+        default: {
+            return vec4f(0.0);
+        }
+    }
+}
+
 fn sphere_sdf(point: vec3f) -> f32 {
     let x = sign(point.x) * (point.x % 1.0);
     let y = sign(point.y) * (point.y % 1.0);
 
     let instance = vec3f(x, y, point.z) - vec3f(0.5);
 
+    orbit_trap = length(instance);
+    iteration_count = 0;
+    iteration_limit = 1;
+
     return length(instance) - 0.15;
 
 }
 
 fn sierpinsky_sdf(point: vec3f) -> f32 {
-    let max_iterations = 3;
-    let scale = 0.5;
+    let max_iterations = i32(uniforms.sierpinski_iterations);
+    let scale = uniforms.sierpinski_scale;
 
     var p = point;
 
@@ -106,6 +680,10 @@ fn sierpinsky_sdf(point: vec3f) -> f32 {
 
     var dist = 0.0;
 
+    orbit_trap = 1e10;
+    iteration_count = max_iterations;
+    iteration_limit = max_iterations;
+
     for (var steps = 0; steps < max_iterations; steps++) {
         c = a1;
         dist = length(p - a1);
@@ -128,6 +706,7 @@ fn sierpinsky_sdf(point: vec3f) -> f32 {
             dist = d;
         }
 
+        orbit_trap = min(orbit_trap, dist);
         p = scale * p - c * (scale - 1.0);
     }
 
@@ -135,14 +714,14 @@ fn sierpinsky_sdf(point: vec3f) -> f32 {
 }
 
 fn box_fold(point: vec3f, dr: f32) -> vec3f {
-    let fold_limit = 1.0;
+    let fold_limit = uniforms.mandelbox_fold_limit;
     return (2.0 * clamp(point, vec3f(-fold_limit), vec3f(fold_limit))) - point;
 }
 
 fn sphere_fold(point: vec3f, dr: f32) -> vec4f {
     let radius = length(point);
-    let min_radius = 0.1;
-    let max_radius = 1.0;
+    let min_radius = uniforms.mandelbox_min_radius;
+    let max_radius = uniforms.mandelbox_max_radius;
 
     if radius < min_radius {
         let ratio = max_radius / min_radius;
@@ -158,12 +737,18 @@ fn sphere_fold(point: vec3f, dr: f32) -> vec4f {
 }
 
 fn mandelbox_sdf(point: vec3f) -> f32 {
-    let max_iterations = 39;
-    let scale = 3.0;
+    let max_iterations = i32(uniforms.mandelbox_iterations);
+    let scale = uniforms.mandelbox_scale;
 
     var p = point;
     var dr: f32 = 1.0;
 
+    // Iterations until the orbit leaves this radius, for the debug views
+    let escape_radius = 100.0;
+    orbit_trap = 1e10;
+    iteration_count = max_iterations;
+    iteration_limit = max_iterations;
+
     for (var steps = 0; steps < max_iterations; steps++) {
         p = box_fold(p, dr);
 
@@ -173,72 +758,403 @@ fn mandelbox_sdf(point: vec3f) -> f32 {
 
         p = (scale * p) + point;
         dr = dr * abs(scale) + 1.0;
+
+        orbit_trap = min(orbit_trap, length(p));
+        if iteration_count == max_iterations && length(p) > escape_radius {
+            iteration_count = steps;
+        }
     }
     return length(p) / abs(dr);
 }
 
 fn sdf(point: vec3f) -> f32 {
-    return sphere_sdf(point);
+    switch uniforms.fractal {
+        case 1u: { return sierpinsky_sdf(point); }
+        case 2u: { return mandelbox_sdf(point); }
+        default: { return sphere_sdf(point); }
+    }
+}
+
+// Analytic gradients of the distance estimators above. These only need to point the right way,
+// so they're left unnormalised
+
+fn sphere_sdf_gradient(point: vec3f) -> vec3f {
+    let x = sign(point.x) * (point.x % 1.0);
+    let y = sign(point.y) * (point.y % 1.0);
+
+    let instance = vec3f(x, y, point.z) - vec3f(0.5);
+
+    // Chain rule through the mirrored repetition
+    return instance * vec3f(sign(point.x), sign(point.y), 1.0);
 }
 
-const max_steps = 1000;
-const max_distance = 1000.0;
-const min_distance = 0.000001;
+fn sierpinsky_sdf_gradient(point: vec3f) -> vec3f {
+    let max_iterations = i32(uniforms.sierpinski_iterations);
+    let scale = uniforms.sierpinski_scale;
+
+    var p = point;
+
+    let a1 = vec3f(1.0, 1.0, 1.0);
+    let a2 = vec3f(-1.0, -1.0, 1.0);
+    let a3 = vec3f(1.0, -1.0, -1.0);
+    let a4 = vec3f(-1.0, 1.0, -1.0);
 
-const normal_sampling_distance = 0.000001;
+    for (var steps = 0; steps < max_iterations; steps++) {
+        var c = a1;
+        var dist = length(p - a1);
+        if length(p - a2) < dist { c = a2; dist = length(p - a2); }
+        if length(p - a3) < dist { c = a3; dist = length(p - a3); }
+        if length(p - a4) < dist { c = a4; }
 
-fn trace(src: vec3f, direction: vec3f) -> vec4f {
+        p = scale * p - c * (scale - 1.0);
+    }
+
+    // Every iteration scales uniformly, so the Jacobian is a multiple of the identity
+    return p;
+}
+
+// Carries the Jacobian of the iterated point through each fold, and returns the gradient of
+// `length(p)`. The derivative of the running `dr` is ignored, which is the usual approximation.
+// The Jacobian grows like `scale^iterations`, so it's kept divided by its own running derivative
+// to stay within f32 range
+fn mandelbox_sdf_gradient(point: vec3f) -> vec3f {
+    let max_iterations = i32(uniforms.mandelbox_iterations);
+    let scale = uniforms.mandelbox_scale;
+    let fold_limit = uniforms.mandelbox_fold_limit;
+    let min_radius = uniforms.mandelbox_min_radius;
+    let max_radius = uniforms.mandelbox_max_radius;
+
+    let identity = mat3x3f(1, 0, 0, 0, 1, 0, 0, 0, 1);
+
+    var p = point;
+    var jacobian = identity;
+    var dr = 1.0;
+
+    for (var steps = 0; steps < max_iterations; steps++) {
+        // Components past the fold limit get reflected
+        let reflection = 1.0 - 2.0 * step(vec3f(fold_limit), abs(p));
+        p = box_fold(p, dr);
+        jacobian = mat3x3f(jacobian[0] * reflection, jacobian[1] * reflection, jacobian[2] * reflection);
+
+        var fold = identity;
+        var ratio = 1.0;
+        let radius = length(p);
+        if radius < min_radius {
+            ratio = max_radius / min_radius;
+            fold = identity * ratio;
+        } else if radius < max_radius {
+            ratio = max_radius / radius;
+            let n = p / radius;
+            fold = (identity - mat3x3f(n * n.x, n * n.y, n * n.z)) * ratio;
+        }
+        p *= ratio;
+
+        p = (scale * p) + point;
+
+        let grown = dr * abs(scale) * ratio + 1.0;
+        jacobian = (fold * jacobian) * (scale * dr / grown) + identity * (1.0 / grown);
+        dr = grown;
+    }
+
+    return transpose(jacobian) * p;
+}
+
+fn sdf_gradient(point: vec3f) -> vec3f {
+    switch uniforms.fractal {
+        case 1u: { return sierpinsky_sdf_gradient(point); }
+        case 2u: { return mandelbox_sdf_gradient(point); }
+        default: { return sphere_sdf_gradient(point); }
+    }
+}
+
+// Fraction of light scattered away along a ray, for exponential fog that thins out with height.
+// See https://iquilezles.org/articles/fog/
+fn fog_amount(src: vec3f, direction: vec3f, distance: f32) -> f32 {
+    let falloff = uniforms.fog_height_falloff;
+    var optical_depth = uniforms.fog_density * distance;
+
+    if falloff > 0.0 {
+        let base = exp(-(src.z - uniforms.fog_height) * falloff);
+        let rise = direction.z * falloff;
+        // Level rays integrate to the same thing as plain distance fog
+        var integral = distance;
+        if abs(rise) > 0.00001 {
+            integral = (1.0 - exp(-distance * rise)) / rise;
+        }
+        optical_depth = uniforms.fog_density * base * integral;
+    }
+
+    return 1.0 - exp(-optical_depth);
+}
+
+// Light from the point light scattered towards the camera by the medium along the ray. Unshadowed,
+// but the inverse square falloff integrates in closed form.
+fn light_scattering(src: vec3f, direction: vec3f, distance: f32) -> vec3f {
+    let to_light = uniforms.light_position - src;
+    let along = dot(to_light, direction);
+    let closest = max(length(to_light - along * direction), 0.0001);
+
+    let integral = (atan((distance - along) / closest) - atan(-along / closest)) / closest;
+    return uniforms.scattering * uniforms.light_color * diffuse_power * integral;
+}
+
+// Fog, glow and in-scattering between the camera and whatever the ray ended on. `distance` is
+// negative for rays that escaped.
+fn atmosphere(src: vec3f, direction: vec3f, distance: f32, steps: i32, surface: vec4f) -> vec4f {
+    var color = surface;
+
+    if distance >= 0.0 {
+        let fog = fog_amount(src, direction, distance);
+        color = vec4f(mix(color.rgb, uniforms.fog_color, fog), color.a);
+    }
+
+    let scatter_distance = select(uniforms.max_distance, distance, distance >= 0.0);
+    let scattered = light_scattering(src, direction, scatter_distance);
+
+    // Rays that brush past the surface take many small steps before escaping
+    let glow = uniforms.glow_color * uniforms.glow_intensity * f32(steps) / f32(uniforms.max_steps);
+
+    let added = scattered + glow;
+    return vec4f(color.rgb + added, max(color.a, min(max(added.r, max(added.g, added.b)), 1.0)));
+}
+
+struct March {
+    hit: bool,
+    distance: f32,
+    steps: i32,
+    // Distance estimate where the march stopped
+    radius: f32,
+}
+
+// Hit threshold at a given distance along the ray: roughly the size of a pixel there, so distant
+// surfaces don't need to be resolved beyond what can be seen
+fn hit_epsilon(distance: f32) -> f32 {
+    let pixel = pixel_footprint * distance + pixel_width;
+    return max(uniforms.min_distance, uniforms.pixel_epsilon * pixel);
+}
+
+// Over-relaxed sphere tracing, falling back to plain steps whenever the unbounding spheres stop
+// overlapping. See Keinert et al., "Enhanced Sphere Tracing" (2014)
+fn march(src: vec3f, direction: vec3f) -> March {
     var total_distance: f32 = 0.0;
+    var omega = uniforms.relaxation;
+    var previous_radius = 0.0;
+    var step_length = 0.0;
 
-    for(var steps = 0; steps < max_steps; steps++) {
-        let current_point = src + (total_distance * direction);
-        let distance_to_surface = sdf(current_point);
+    var steps = 0;
+    for(; steps < i32(uniforms.max_steps); steps++) {
+        let radius = sdf(src + (total_distance * direction));
+
+        let overstepped = omega > 1.0 && (radius + previous_radius) < step_length;
+        if overstepped {
+            // Undo the relaxed part of the last step and carry on conservatively
+            step_length -= omega * step_length;
+            omega = 1.0;
+        } else {
+            step_length = radius * omega;
+        }
+        previous_radius = radius;
+
+        if !overstepped && radius < hit_epsilon(total_distance) {
+            return March(true, total_distance, steps, radius);
+        }
 
-        if distance_to_surface > max_distance {
-            break;
+        if total_distance > uniforms.max_distance || radius > uniforms.max_distance {
+            return March(false, total_distance, steps, radius);
         }
 
-        // Adaptive step size: smaller steps when close to surfaces
-        let step_size = max(distance_to_surface, min_distance * (1.0 + total_distance * 0.1));
-        total_distance += step_size;
+        total_distance += step_length;
+    }
+
+    return March(false, total_distance, steps, previous_radius);
+}
+
+// Finite difference normals, sampled at the scale the surface was resolved to
+fn estimate_normal(point: vec3f, distance: f32) -> vec3f {
+    let h = max(uniforms.normal_sampling_distance, 0.5 * hit_epsilon(distance));
 
-        var lambertian = 0.0;
-        if distance_to_surface < min_distance {
-            // Approximate normal with finite differences
-            let dx = normal_sampling_distance * vec3f(1, 0, 0);
-            let dy = normal_sampling_distance * vec3f(0, 1, 0);
-            let dz = normal_sampling_distance * vec3f(0, 0, 1);
-            let normal = normalize(vec3f(
-                sdf(current_point + dx) - sdf(current_point - dx),
-                sdf(current_point + dy) - sdf(current_point - dy),
-                sdf(current_point + dz) - sdf(current_point - dz),
+    switch uniforms.normal_estimator {
+        case 1u: {
+            // Central differences, six taps
+            let dx = h * vec3f(1, 0, 0);
+            let dy = h * vec3f(0, 1, 0);
+            let dz = h * vec3f(0, 0, 1);
+            return normalize(vec3f(
+                sdf(point + dx) - sdf(point - dx),
+                sdf(point + dy) - sdf(point - dy),
+                sdf(point + dz) - sdf(point - dz),
             ));
+        }
+        case 2u: {
+            let gradient = sdf_gradient(point);
+            if dot(gradient, gradient) > 0.0 {
+                return normalize(gradient);
+            }
+            return tetrahedral_normal(point, h);
+        }
+        default: {
+            return tetrahedral_normal(point, h);
+        }
+    }
+}
+
+// Four taps on the corners of a tetrahedron. See https://iquilezles.org/articles/normalsSDF/
+fn tetrahedral_normal(point: vec3f, h: f32) -> vec3f {
+    let k = vec2f(1.0, -1.0);
+    return normalize(
+        k.xyy * sdf(point + k.xyy * h) +
+        k.yyx * sdf(point + k.yyx * h) +
+        k.yxy * sdf(point + k.yxy * h) +
+        k.xxx * sdf(point + k.xxx * h)
+    );
+}
 
-            // Get light vectors
-            var light_direction = light_position - current_point;
-            let light_distance = dot(light_direction, light_direction);
-            light_direction = normalize(light_direction);
+fn shade(point: vec3f, normal: vec3f, direction: vec3f) -> vec3f {
+    // Get light vectors
+    var light_direction = uniforms.light_position - point;
+    let light_distance = dot(light_direction, light_direction);
+    light_direction = normalize(light_direction);
 
-            lambertian = max(dot(normal, light_direction), 0.0);
+    let lambertian = max(dot(normal, light_direction), 0.0);
 
-            // Blinn-Phong shading
-            var specular = 0.0;
-            if lambertian != 0 {
-                let halfway = normalize(light_direction + camera_direction);
-                let specular_angle = max(dot(halfway, normal), 0.0);
-                specular = pow(specular_angle, shininess);
+    // Blinn-Phong shading
+    var specular = 0.0;
+    if lambertian != 0 {
+        let halfway = normalize(light_direction - direction);
+        let specular_angle = max(dot(halfway, normal), 0.0);
+        specular = pow(specular_angle, uniforms.shininess);
+    }
+    // Image based lighting replaces the flat ambient term when an environment is loaded
+    var ambient = ambient_color;
+    var reflection = vec3f(0.0);
+    if uniforms.environment_enabled != 0u {
+        ambient = uniforms.diffuse_color * environment_irradiance(normal);
+        reflection = uniforms.specular_color *
+            environment_radiance(reflect(direction, normal), uniforms.shininess);
+    }
+
+    // Linear colorspace intensity mix
+    let linear_color = ambient + reflection +
+                        uniforms.diffuse_color * lambertian * uniforms.light_color * diffuse_power / light_distance +
+                        uniforms.specular_color * specular * uniforms.light_color * specular_power / light_distance;
+    return linear_color;
+}
+
+// Ambient occlusion from a few distance estimates along the normal. See
+// https://iquilezles.org/articles/nvscene2008/rwwtt.pdf
+fn ambient_occlusion(point: vec3f, normal: vec3f) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i = 0; i < 5; i++) {
+        let h = 0.01 + 0.12 * f32(i) / 4.0;
+        occlusion += (h - sdf(point + h * normal)) * weight;
+        weight *= 0.95;
+    }
+    return clamp(1.0 - 3.0 * occlusion, 0.0, 1.0);
+}
+
+// One sample's contribution to the AOVs, for a surface hit at `distance` along the ray
+fn surface_aov(point: vec3f, distance: f32) -> Aov {
+    // Planar projections measure depth to the image plane, the rest to the camera
+    let offset = point - uniforms.camera_position;
+    var depth = length(offset);
+    if uniforms.projection <= 1u {
+        depth = dot(offset, camera_basis().forward);
+    }
+
+    sdf(point);
+    let trap = orbit_trap;
+    let iterations = f32(iteration_count);
+
+    let normal = estimate_normal(point, distance);
+    return Aov(
+        vec4f(point, depth),
+        vec4f(normal, ambient_occlusion(point, normal)),
+        vec4f(trap, iterations, 1.0, 0.0),
+    );
+}
+
+// Black, through blue, red and yellow, to white
+fn heatmap(t: f32) -> vec3f {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    var stops = array(
+        vec3f(0.0, 0.0, 0.0),
+        vec3f(0.1, 0.1, 0.8),
+        vec3f(0.9, 0.1, 0.1),
+        vec3f(1.0, 0.9, 0.1),
+        vec3f(1.0, 1.0, 1.0),
+    );
+    let i = min(u32(x), 3u);
+    return mix(stops[i], stops[i + 1u], x - f32(i));
+}
+
+// Views for working out why a fractal renders the way it does. Modes that only make sense on a
+// surface show misses as black
+fn debug_view(point: vec3f, direction: vec3f, march: March) -> vec4f {
+    switch uniforms.render_mode {
+        case 2u: {
+            return vec4f(heatmap(f32(march.steps) / f32(uniforms.max_steps)), 1.0);
+        }
+        case 6u: {
+            if march.hit {
+                return vec4f(0.1, 0.8, 0.1, 1.0);
             }
-            // Fog
-            let fog = vec3f(f32(steps) / f32(max_steps));
+            if march.steps < i32(uniforms.max_steps) {
+                // Escaped past the far plane
+                return vec4f(0.1, 0.2, 0.8, 1.0);
+            }
+            if march.radius < 10.0 * hit_epsilon(march.distance) {
+                // Ran out of steps right next to a surface, probably a hole
+                return vec4f(0.9, 0.8, 0.1, 1.0);
+            }
+            return vec4f(0.8, 0.1, 0.1, 1.0);
+        }
+        default: {}
+    }
 
-            // Linear colorspace intensity mix
-            let linear_color = ambient_color +
-                                diffuse_color * lambertian * light_color * diffuse_power / light_distance +
-                                specular_color * specular * light_color * specular_power / light_distance;
-            let gamma_corrected = pow(linear_color, vec3(1.0 / gamma));
-            return vec4f(linear_color, 1.0);
+    if !march.hit {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+
+    switch uniforms.render_mode {
+        case 1u: {
+            let normal = estimate_normal(point, march.distance);
+            return vec4f(normal * 0.5 + 0.5, 1.0);
+        }
+        case 3u: {
+            return vec4f(vec3f(1.0 - march.distance / uniforms.depth_range), 1.0);
+        }
+        case 4u: {
+            sdf(point);
+            return vec4f(heatmap(orbit_trap), 1.0);
+        }
+        case 5u: {
+            sdf(point);
+            return vec4f(heatmap(f32(iteration_count) / f32(iteration_limit)), 1.0);
+        }
+        default: {
+            return vec4f(1.0, 0.0, 1.0, 1.0);
         }
     }
+}
+
+fn trace(src: vec3f, direction: vec3f) -> vec4f {
+    let march = march(src, direction);
+    let point = src + march.distance * direction;
+
+    hit_distance = select(-1.0, march.distance, march.hit);
+    hit_point = point;
+
+    if uniforms.render_mode != 0u {
+        return debug_view(point, direction, march);
+    }
+
+    if !march.hit {
+        return atmosphere(src, direction, -1.0, march.steps, background(direction));
+    }
+
+    let normal = estimate_normal(point, march.distance);
+    let color = shade(point, normal, direction);
 
-    return vec4f(0.5, 0, 0, 0);
+    return atmosphere(src, direction, march.distance, march.steps, vec4f(color, 1.0));
 }
@@ -1,3 +1,4 @@
 pub mod pipeline;
+pub mod post;
 pub mod primitive;
 pub mod program;
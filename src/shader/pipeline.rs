@@ -1,28 +1,261 @@
+use std::sync::Arc;
+
 use bytemuck::bytes_of;
-use glam::Vec2;
-use iced::{
-    widget::shader::{self, wgpu},
-    Rectangle,
+use glam::Vec3;
+use half::f16;
+use iced::widget::shader::wgpu;
+
+use crate::{
+    background::Background,
+    camera::FovAxis,
+    environment::EnvironmentMap,
+    lut::{Look, Lut},
+    post::PostEffect,
+    scene::Scene,
+    shader::post::PostProcessPipeline,
+    tone_mapping::ToneMapping,
 };
 
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct Uniforms {
-    resolution: Vec2,
-    center: Vec2,
-    scale: f32,
-    max_iter: u32,
+    camera_position: Vec3,
+    environment_intensity: f32,
+    camera_direction: Vec3,
+    environment_rotation: f32,
+    background_color: Vec3,
+    environment_enabled: u32,
+    background_secondary_color: Vec3,
+    background_mode: u32,
+    sun_direction: Vec3,
+    sun_intensity: f32,
+    environment_levels: f32,
+    fog_density: f32,
+    fog_height: f32,
+    fog_height_falloff: f32,
+    fog_color: Vec3,
+    glow_intensity: f32,
+    glow_color: Vec3,
+    scattering: f32,
+    max_steps: u32,
+    max_distance: f32,
+    min_distance: f32,
+    normal_sampling_distance: f32,
+    pixel_epsilon: f32,
+    relaxation: f32,
+    normal_estimator: u32,
+    render_mode: u32,
+    depth_range: f32,
+    aperture: f32,
+    focus_distance: f32,
+    frame: u32,
+    fov: f32,
+    fov_axis: u32,
+    projection: u32,
+    orthographic_size: f32,
+    panini_distance: f32,
+    stereo_mode: u32,
+    interocular_distance: f32,
+    convergence_distance: f32,
+    previous_camera_position: Vec3,
+    seed: u32,
+    previous_camera_direction: Vec3,
+    temporal: u32,
+    render_size: [u32; 2],
+    previous_render_size: [u32; 2],
+    light_position: Vec3,
+    fractal: u32,
+    light_color: Vec3,
+    aovs: u32,
+    diffuse_color: Vec3,
+    shininess: f32,
+    specular_color: Vec3,
+    sierpinski_iterations: u32,
+    sierpinski_scale: f32,
+    mandelbox_iterations: u32,
+    mandelbox_scale: f32,
+    mandelbox_fold_limit: f32,
+    mandelbox_min_radius: f32,
+    mandelbox_max_radius: f32,
+    _padding: [u32; 2],
+}
+
+impl Uniforms {
+    pub fn new(scene: &Scene) -> Self {
+        let (background_color, background_secondary_color) = match scene.background {
+            Background::Solid(color) => (color, Vec3::ZERO),
+            Background::Gradient { top, bottom } => (top, bottom),
+            _ => (Vec3::ZERO, Vec3::ZERO),
+        };
+        let (sun_direction, sun_intensity) = match scene.background {
+            Background::Sky {
+                sun_direction,
+                sun_intensity,
+            } => (sun_direction.normalize_or_zero(), sun_intensity),
+            _ => (Vec3::Z, 0.0),
+        };
+        let sierpinski = &scene.fractal_parameters.sierpinski;
+        let mandelbox = &scene.fractal_parameters.mandelbox;
+
+        Self {
+            camera_position: scene.camera.position,
+            environment_intensity: scene.environment.intensity,
+            camera_direction: scene.camera.direction,
+            environment_rotation: scene.environment.rotation,
+            background_color,
+            environment_enabled: scene.environment.map.is_some() as u32,
+            background_secondary_color,
+            background_mode: scene.background.mode(),
+            sun_direction,
+            sun_intensity,
+            // `textureNumLevels` isn't available on every backend
+            environment_levels: scene
+                .environment
+                .map
+                .as_ref()
+                .map_or(1.0, |map| map.levels.len() as f32),
+            fog_density: scene.atmosphere.fog_density,
+            fog_height: scene.atmosphere.fog_height,
+            fog_height_falloff: scene.atmosphere.fog_height_falloff,
+            fog_color: scene.atmosphere.fog_color,
+            glow_intensity: scene.atmosphere.glow_intensity,
+            glow_color: scene.atmosphere.glow_color,
+            scattering: scene.atmosphere.scattering,
+            max_steps: scene.marcher.max_steps,
+            max_distance: scene.marcher.max_distance,
+            min_distance: scene.marcher.min_distance,
+            normal_sampling_distance: scene.marcher.normal_sampling_distance,
+            pixel_epsilon: scene.marcher.pixel_epsilon,
+            relaxation: scene.marcher.relaxation,
+            normal_estimator: scene.marcher.normal_estimator.index(),
+            render_mode: scene.render_mode.index(),
+            depth_range: scene.depth_range,
+            aperture: scene.camera.aperture,
+            focus_distance: scene.camera.focus_distance,
+            frame: 0,
+            fov: scene.camera.fov.to_radians(),
+            fov_axis: match scene.camera.fov_axis {
+                FovAxis::Vertical => 0,
+                FovAxis::Horizontal => 1,
+            },
+            projection: scene.camera.projection.index(),
+            orthographic_size: scene.camera.orthographic_size,
+            panini_distance: scene.camera.panini_distance,
+            stereo_mode: scene.stereo.mode.index(),
+            interocular_distance: scene.stereo.interocular_distance,
+            convergence_distance: scene.stereo.convergence_distance,
+            previous_camera_position: scene.camera.position,
+            seed: 0,
+            previous_camera_direction: scene.camera.direction,
+            temporal: 0,
+            render_size: [0; 2],
+            previous_render_size: [0; 2],
+            light_position: scene.light.position,
+            fractal: scene.fractal.index(),
+            light_color: scene.light.color,
+            aovs: 0,
+            diffuse_color: scene.material.diffuse_color,
+            shininess: scene.material.shininess,
+            specular_color: scene.material.specular_color,
+            sierpinski_iterations: sierpinski.iterations,
+            sierpinski_scale: sierpinski.scale,
+            mandelbox_iterations: mandelbox.iterations,
+            mandelbox_scale: mandelbox.scale,
+            mandelbox_fold_limit: mandelbox.fold_limit,
+            mandelbox_min_radius: mandelbox.min_radius,
+            mandelbox_max_radius: mandelbox.max_radius,
+            _padding: [0; 2],
+        }
+    }
+
+    /// Whether an image rendered with `previous` can be reprojected to these uniforms, i.e. only
+    /// the camera pose or resolution differs and the projection is one `reproject` in
+    /// `shader.wgsl` handles.
+    fn can_reproject(&self, previous: &Self) -> bool {
+        let moved = Self {
+            camera_position: self.camera_position,
+            camera_direction: self.camera_direction,
+            render_size: self.render_size,
+            ..*previous
+        };
+        moved == *self && self.projection <= 1 && self.stereo_mode == 0
+    }
+}
+
+/// Bytes per pixel in the AOV buffer, the size of the shader's `Aov`.
+const AOV_SIZE: u64 = 48;
+
+/// Per pixel storage buffers.
+struct SampleBuffers {
+    /// Running sum of every sample so far, as one `vec4<f32>` per pixel
+    accumulation: wgpu::Buffer,
+    /// This frame's samples, before resolving
+    current: wgpu::Buffer,
+    /// The last two resolved frames, read and written alternately
+    history: [wgpu::Buffer; 2],
+    /// Summed surface attributes, three `vec4<f32>` per pixel once enabled
+    aovs: wgpu::Buffer,
+}
+
+impl SampleBuffers {
+    fn new(device: &wgpu::Device, target_size: iced::Size<u32>) -> Self {
+        // Both `vec4<f32>` and the shader's packed `Sample` are 16 bytes
+        let size = (target_size.width * target_size.height) as u64 * 16;
+        let buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self {
+            accumulation: buffer("accumulation buffer"),
+            current: buffer("current sample buffer"),
+            history: [buffer("history buffer"), buffer("history buffer")],
+            // Placeholder until `ComputeShaderPipeline::enable_aovs`
+            aovs: create_aov_buffer(device, 1),
+        }
+    }
+}
+
+/// Room for the AOVs of `pixels` pixels.
+fn create_aov_buffer(device: &wgpu::Device, pixels: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("AOV buffer"),
+        size: pixels * AOV_SIZE,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
 }
 
 pub struct ComputeShaderPipeline {
     pipeline: wgpu::ComputePipeline,
-    // uniform_buffer: wgpu::Buffer,
+    resolve_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    uniforms: Option<Uniforms>,
+    /// Samples accumulated since the uniforms last changed
+    frame: u32,
+    /// Dispatches so far, for seeding the random numbers
+    seed: u32,
+    buffers: SampleBuffers,
     pub screen_texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
+    screen_texture_view: wgpu::TextureView,
+    environment: Option<Arc<EnvironmentMap>>,
+    environment_view: wgpu::TextureView,
+    environment_sampler: wgpu::Sampler,
+    /// One per direction the history buffers can be swapped
+    bind_groups: [wgpu::BindGroup; 2],
+    /// Which of `bind_groups` the next dispatch uses
+    history: usize,
+    /// Whether the AOV buffer covers the screen and is written to
+    aovs: bool,
 }
 
 impl ComputeShaderPipeline {
-    pub fn new(device: &wgpu::Device, target_size: iced::Size<u32>) -> Self {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, target_size: iced::Size<u32>) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("compute shader module"),
             source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
@@ -37,6 +270,8 @@ impl ComputeShaderPipeline {
             mapped_at_creation: false,
         });
 
+        let buffers = SampleBuffers::new(device, target_size);
+
         let screen_texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("screen storage texture"),
             size: wgpu::Extent3d {
@@ -47,42 +282,81 @@ impl ComputeShaderPipeline {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            format: wgpu::TextureFormat::Rgba16Float,
             usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
         let screen_texture_view =
             screen_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        // Placeholder bound to channel0 until an environment map is loaded
+        let environment_view =
+            create_environment_texture(device, queue, &EnvironmentMap::placeholder());
+        let environment_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("bind group layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::StorageTexture {
-                    access: wgpu::StorageTextureAccess::WriteOnly,
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    view_dimension: wgpu::TextureViewDimension::D2,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
-        });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("shader bind group"),
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&screen_texture_view),
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_buffer_entry(4, false),
+                storage_buffer_entry(5, false),
+                storage_buffer_entry(6, true),
+                storage_buffer_entry(7, false),
+                storage_buffer_entry(8, false),
+            ],
         });
-        //         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        //             label: Some("uniform bind group"),
-        //             layout: &uniform_bind_group_layout,
-        //             entries: &[wgpu::BindGroupEntry {
-        //                 binding: 0,
-        //                 resource: uniform_buffer.as_entire_binding(),
-        //             }],
-        //         });
+        let bind_groups = create_bind_groups(
+            device,
+            &bind_group_layout,
+            &screen_texture_view,
+            &environment_view,
+            &environment_sampler,
+            &uniform_buffer,
+            &buffers,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("compute pipeline layout"),
@@ -95,18 +369,183 @@ impl ComputeShaderPipeline {
             module: &shader,
             entry_point: "main_image",
         });
+        let resolve_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("resolve shader pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "resolve",
+        });
 
         Self {
             pipeline,
-            // uniform_buffer,
+            resolve_pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            uniforms: None,
+            frame: 0,
+            seed: 0,
+            buffers,
             screen_texture,
-            bind_group,
+            screen_texture_view,
+            environment: None,
+            environment_view,
+            environment_sampler,
+            bind_groups,
+            history: 0,
+            aovs: false,
         }
     }
 
-    // fn update(&mut self, queue: &wgpu::Queue, uniforms: &Uniforms) {
-    //     queue.write_buffer(&self.uniform_buffer, 0, bytes_of(uniforms));
-    // }
+    pub fn size(&self) -> iced::Size<u32> {
+        iced::Size::new(self.screen_texture.width(), self.screen_texture.height())
+    }
+
+    /// Writes the uniforms for the next dispatch. Every call adds another sample to the
+    /// accumulated image, which starts over whenever the uniforms change. If only the camera
+    /// moved, the last frame is reprojected and blended in instead of thrown away.
+    ///
+    /// `render_scale` shrinks the area rendered, to the top left of the screen texture.
+    pub fn update(&mut self, queue: &wgpu::Queue, uniforms: &Uniforms, render_scale: f32) {
+        let size = self.size();
+        let scaled = |length: u32| ((length as f32 * render_scale) as u32).clamp(1, length);
+        let uniforms = &Uniforms {
+            render_size: [scaled(size.width), scaled(size.height)],
+            ..*uniforms
+        };
+
+        let previous = self.uniforms.unwrap_or(*uniforms);
+        let temporal = self
+            .uniforms
+            .is_some_and(|previous| previous != *uniforms && uniforms.can_reproject(&previous));
+
+        if self.uniforms.as_ref() != Some(uniforms) {
+            self.uniforms = Some(*uniforms);
+            self.frame = 0;
+        }
+
+        let uniforms = Uniforms {
+            frame: self.frame,
+            seed: self.seed,
+            previous_camera_position: previous.camera_position,
+            previous_camera_direction: previous.camera_direction,
+            temporal: temporal as u32,
+            previous_render_size: previous.render_size,
+            aovs: self.aovs as u32,
+            ..*uniforms
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytes_of(&uniforms));
+
+        self.frame += 1;
+        self.seed = self.seed.wrapping_add(1);
+        self.history = 1 - self.history;
+    }
+
+    /// Writes the uniforms for the next dispatch at full resolution, adding another sample to the
+    /// accumulated image even if they changed. Used for the sub-frames of motion blur, which
+    /// should average together rather than start over.
+    pub fn accumulate(&mut self, queue: &wgpu::Queue, uniforms: &Uniforms) {
+        let size = self.size();
+        let uniforms = &Uniforms {
+            render_size: [size.width, size.height],
+            ..*uniforms
+        };
+        self.uniforms = Some(*uniforms);
+
+        let uniforms = Uniforms {
+            frame: self.frame,
+            seed: self.seed,
+            previous_camera_position: uniforms.camera_position,
+            previous_camera_direction: uniforms.camera_direction,
+            temporal: 0,
+            previous_render_size: uniforms.render_size,
+            aovs: self.aovs as u32,
+            ..*uniforms
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytes_of(&uniforms));
+
+        self.frame += 1;
+        self.seed = self.seed.wrapping_add(1);
+        self.history = 1 - self.history;
+    }
+
+    /// Pixels rendered by the next dispatch, from the top left of the screen texture.
+    pub fn render_size(&self) -> iced::Size<u32> {
+        let [width, height] = self
+            .uniforms
+            .map_or([0; 2], |uniforms| uniforms.render_size);
+        iced::Size::new(width, height)
+    }
+
+    /// Running sum of every sample so far, as one `vec4<f32>` per pixel.
+    pub fn accumulation_buffer(&self) -> &wgpu::Buffer {
+        &self.buffers.accumulation
+    }
+
+    /// Samples accumulated so far, including the one about to be dispatched.
+    pub fn frames(&self) -> u32 {
+        self.frame
+    }
+
+    /// Starts summing surface attributes for compositing alongside the image, at the cost of a
+    /// buffer three times the size of the accumulation buffer.
+    pub fn enable_aovs(&mut self, device: &wgpu::Device) {
+        let size = self.size();
+        self.buffers.aovs = create_aov_buffer(device, (size.width * size.height) as u64);
+        self.aovs = true;
+        self.reset();
+        self.bind_groups = create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.screen_texture_view,
+            &self.environment_view,
+            &self.environment_sampler,
+            &self.uniform_buffer,
+            &self.buffers,
+        );
+    }
+
+    /// Surface attributes summed so far, as laid out by the shader's `Aov`, if enabled.
+    pub fn aov_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.aovs.then_some(&self.buffers.aovs)
+    }
+
+    /// Throws away the accumulated samples, e.g. once the environment changes.
+    pub fn reset(&mut self) {
+        self.uniforms = None;
+    }
+
+    /// Uploads a new environment map, if it differs from the one currently bound.
+    pub fn set_environment(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        environment: Option<&Arc<EnvironmentMap>>,
+    ) {
+        let unchanged = match (&self.environment, environment) {
+            (Some(current), Some(new)) => Arc::ptr_eq(current, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+
+        self.environment = environment.cloned();
+        self.reset();
+        self.environment_view = match environment {
+            Some(map) => create_environment_texture(device, queue, map),
+            None => create_environment_texture(device, queue, &EnvironmentMap::placeholder()),
+        };
+        self.bind_groups = create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.screen_texture_view,
+            &self.environment_view,
+            &self.environment_sampler,
+            &self.uniform_buffer,
+            &self.buffers,
+        );
+    }
 
     pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
@@ -114,28 +553,190 @@ impl ComputeShaderPipeline {
             timestamp_writes: None,
         });
 
-        pass.set_pipeline(&self.pipeline);
-        pass.set_bind_group(0, &self.bind_group, &[]);
-
+        let render_size = self.render_size();
         let workgroup_size = (8, 8);
         let workgroups = (
-            (self.screen_texture.width() + workgroup_size.0 - 1) / workgroup_size.0,
-            (self.screen_texture.height() + workgroup_size.1 - 1) / workgroup_size.1,
+            render_size.width.div_ceil(workgroup_size.0),
+            render_size.height.div_ceil(workgroup_size.1),
         );
 
+        // Passes synchronise storage writes, so the resolve can read this frame's neighbours
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_groups[self.history], &[]);
         pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        drop(pass);
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Shader Pipeline resolve pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.resolve_pipeline);
+        pass.set_bind_group(0, &self.bind_groups[self.history], &[]);
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+}
+
+fn storage_buffer_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Creates a bind group for each way round the history buffers can be read and written.
+fn create_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    screen_texture_view: &wgpu::TextureView,
+    environment_view: &wgpu::TextureView,
+    environment_sampler: &wgpu::Sampler,
+    uniform_buffer: &wgpu::Buffer,
+    buffers: &SampleBuffers,
+) -> [wgpu::BindGroup; 2] {
+    [0, 1].map(|read| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shader bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(screen_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(environment_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(environment_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.accumulation.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffers.current.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffers.history[read].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: buffers.history[1 - read].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: buffers.aovs.as_entire_binding(),
+                },
+            ],
+        })
+    })
+}
+
+/// Uploads an environment map's mip chain as an `rgba16float` texture.
+///
+/// Levels larger than the device allows are skipped, so very large HDRIs get downsampled rather
+/// than failing to load.
+fn create_environment_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    map: &EnvironmentMap,
+) -> wgpu::TextureView {
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    let levels: Vec<_> = map
+        .levels
+        .iter()
+        .skip_while(|level| level.width > max_dimension || level.height > max_dimension)
+        .collect();
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("environment texture"),
+        size: wgpu::Extent3d {
+            width: levels[0].width,
+            height: levels[0].height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: levels.len() as u32,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for (mip_level, level) in levels.iter().enumerate() {
+        let pixels: Vec<f16> = level.pixels.iter().copied().map(f16::from_f32).collect();
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: mip_level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(level.width * 8),
+                rows_per_image: Some(level.height),
+            },
+            wgpu::Extent3d {
+                width: level.width,
+                height: level.height,
+                depth_or_array_layers: 1,
+            },
+        );
     }
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Mirrors `Display` in `render.wgsl`.
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct DisplayUniforms {
+    region: [f32; 2],
+    exposure: f32,
+    tone_mapper: u32,
+    encode_srgb: u32,
+    _padding: u32,
+    origin: [f32; 2],
+    lut_domain_min: [f32; 3],
+    lut_size: f32,
+    lut_domain_max: [f32; 3],
+    lut_strength: f32,
 }
 
 pub struct RenderShaderPipeline {
     pipeline: wgpu::RenderPipeline,
-    sampled_texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
+    post: PostProcessPipeline,
+    display_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    lut: Option<Arc<Lut>>,
+    lut_view: wgpu::TextureView,
+    /// One for each of the post stack's output views
+    bind_groups: [wgpu::BindGroup; 3],
+    /// Whether the target needs sRGB encoding, or its format does it already
+    encode_srgb: bool,
 }
 
 impl RenderShaderPipeline {
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         format: wgpu::TextureFormat,
         target_size: iced::Size<u32>,
     ) -> Self {
@@ -146,28 +747,23 @@ impl RenderShaderPipeline {
             ))),
         });
 
-        let sampled_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("sampled screen texture"),
-            size: wgpu::Extent3d {
-                width: target_size.width,
-                height: target_size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-        let sampled_texture_view =
-            sampled_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let post = PostProcessPipeline::new(device, target_size);
 
+        // Linear filtering does the upscaling when rendering below full resolution
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("render sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
+        let display_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("display uniform buffer"),
+            size: std::mem::size_of::<DisplayUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("render bind group layout"),
             entries: &[
@@ -187,24 +783,40 @@ impl RenderShaderPipeline {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("render bind group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&sampled_texture_view),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
                 },
             ],
         });
 
+        // Placeholder bound until a LUT is loaded
+        let lut_view = create_lut_texture(device, queue, &Lut::identity());
+        let bind_groups = create_render_bind_groups(
+            device,
+            &bind_group_layout,
+            &post,
+            &sampler,
+            &display_buffer,
+            &lut_view,
+        );
+
         let vertex_state = wgpu::VertexState {
             module: &shader,
             entry_point: "vs_main",
@@ -253,36 +865,98 @@ impl RenderShaderPipeline {
 
         Self {
             pipeline,
-            sampled_texture,
-            bind_group,
+            post,
+            display_buffer,
+            bind_group_layout,
+            sampler,
+            lut: None,
+            lut_view,
+            bind_groups,
+            encode_srgb: !format.is_srgb(),
         }
     }
 
+    /// Uploads a new grading LUT, if it differs from the one currently bound.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: Option<&Arc<Lut>>) {
+        let unchanged = match (&self.lut, lut) {
+            (Some(current), Some(new)) => Arc::ptr_eq(current, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+
+        self.lut = lut.cloned();
+        self.lut_view = match lut {
+            Some(lut) => create_lut_texture(device, queue, lut),
+            None => create_lut_texture(device, queue, &Lut::identity()),
+        };
+        self.bind_groups = create_render_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.post,
+            &self.sampler,
+            &self.display_buffer,
+            &self.lut_view,
+        );
+    }
+
+    /// Sets where the view's top left is in the target, in pixels, how much of the screen texture
+    /// was rendered to, to stretch over the whole view, and how to post process, tone map and
+    /// grade it.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        origin: iced::Point,
+        render_size: iced::Size<u32>,
+        effects: &[PostEffect],
+        tone_mapping: &ToneMapping,
+        look: &Look,
+    ) {
+        self.post.update(queue, effects, render_size);
+
+        // Effects render at full resolution, so only the unprocessed image needs stretching
+        let texture = self.post.output_texture();
+        let region = match self.post.output() {
+            0 => [
+                render_size.width as f32 / texture.width() as f32,
+                render_size.height as f32 / texture.height() as f32,
+            ],
+            _ => [1.0, 1.0],
+        };
+
+        let identity = Lut::identity();
+        let lut = self.lut.as_deref().unwrap_or(&identity);
+
+        let uniforms = DisplayUniforms {
+            region,
+            exposure: tone_mapping.exposure,
+            tone_mapper: tone_mapping.mapper.index(),
+            encode_srgb: self.encode_srgb as u32,
+            _padding: 0,
+            origin: [origin.x, origin.y],
+            lut_domain_min: lut.domain_min.to_array(),
+            lut_size: lut.size as f32,
+            lut_domain_max: lut.domain_max.to_array(),
+            lut_strength: if self.lut.is_some() {
+                look.strength
+            } else {
+                0.0
+            },
+        };
+        queue.write_buffer(&self.display_buffer, 0, bytes_of(&uniforms));
+    }
+
+    /// Draws the view into `viewport` of the target, leaving the rest of the window as it is.
     pub fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
         target: &wgpu::TextureView,
+        viewport: iced::Rectangle<u32>,
         screen_texture: &wgpu::Texture,
     ) {
-        encoder.copy_texture_to_texture(
-            wgpu::ImageCopyTexture {
-                texture: screen_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::ImageCopyTexture {
-                texture: &self.sampled_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::Extent3d {
-                width: screen_texture.width(),
-                height: screen_texture.height(),
-                depth_or_array_layers: screen_texture.depth_or_array_layers(),
-            },
-        );
+        self.post.process(encoder, screen_texture);
 
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("render pass"),
@@ -290,15 +964,97 @@ impl RenderShaderPipeline {
                 view: target,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
             ..Default::default()
         });
 
+        pass.set_viewport(
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+            0.0,
+            1.0,
+        );
+        pass.set_scissor_rect(viewport.x, viewport.y, viewport.width, viewport.height);
         pass.set_pipeline(&self.pipeline);
-        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(0, &self.bind_groups[self.post.output()], &[]);
         pass.draw(0..3, 0..1);
     }
 }
+
+fn create_render_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    post: &PostProcessPipeline,
+    sampler: &wgpu::Sampler,
+    display_buffer: &wgpu::Buffer,
+    lut_view: &wgpu::TextureView,
+) -> [wgpu::BindGroup; 3] {
+    post.output_views().map(|view| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("render bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: display_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(lut_view),
+                },
+            ],
+        })
+    })
+}
+
+fn create_lut_texture(device: &wgpu::Device, queue: &wgpu::Queue, lut: &Lut) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: lut.size,
+        height: lut.size,
+        depth_or_array_layers: lut.size,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("lut texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    // The table's order, red fastest, is already the texture's x, y, z order
+    let texels: Vec<f16> = lut
+        .table
+        .iter()
+        .flat_map(|entry| entry.extend(1.0).to_array())
+        .map(f16::from_f32)
+        .collect();
+
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(lut.size * 8),
+            rows_per_image: Some(lut.size),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
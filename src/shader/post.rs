@@ -0,0 +1,478 @@
+// Post processing, run on the linear HDR image between the compute shader and tone mapping.
+
+use bytemuck::bytes_of;
+use iced::widget::shader::wgpu;
+
+use crate::post::{Effect, PostEffect};
+
+/// Mirrors `Pass` in `post.wgsl`.
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PassUniforms {
+    region: [f32; 2],
+    output_size: [f32; 2],
+    kind: u32,
+    _padding: [u32; 3],
+    params: [f32; 8],
+}
+
+// Bloom's internal steps, matching the constants in `post.wgsl`
+const BLOOM_DOWNSAMPLE: u32 = 16;
+const BLOOM_PREFILTER: u32 = 17;
+const BLOOM_UPSAMPLE: u32 = 18;
+
+/// Each pass's uniforms sit in their own slot, at the default minimum dynamic offset alignment.
+const SLOT_SIZE: u64 = 256;
+
+/// Plenty for a long stack with a few blooms in it. Effects that would overflow are skipped.
+const MAX_PASSES: usize = 64;
+
+/// Most bloom levels, each half the size of the last.
+const MAX_BLOOM_LEVELS: u32 = 6;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Texture a pass reads from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Input {
+    Source,
+    Stage(usize),
+    Bloom(usize),
+}
+
+/// Texture a pass renders to.
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Stage(usize),
+    Bloom(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pass {
+    input: Input,
+    output: Output,
+    /// Whether the top of the bloom chain is bound, for compositing it
+    composite: bool,
+    /// Whether to add onto the output rather than replace it
+    additive: bool,
+}
+
+pub struct PostProcessPipeline {
+    pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    /// Copy of the compute shader's output, which can't be sampled directly
+    source: wgpu::Texture,
+    /// Effects render back and forth between these
+    stages: [wgpu::Texture; 2],
+    stage_views: [wgpu::TextureView; 2],
+    /// One view per level of the bloom chain, starting at half resolution
+    bloom_views: Vec<wgpu::TextureView>,
+    /// See `bind_group`
+    bind_groups: Vec<wgpu::BindGroup>,
+    source_view: wgpu::TextureView,
+    passes: Vec<Pass>,
+    /// Where the last pass leaves the result
+    output: Input,
+}
+
+impl PostProcessPipeline {
+    pub fn new(device: &wgpu::Device, target_size: iced::Size<u32>) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "../post.wgsl"
+            ))),
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post uniform buffer"),
+            size: SLOT_SIZE * MAX_PASSES as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture = |label, width, height, mip_level_count, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let (width, height) = (target_size.width, target_size.height);
+        let stage_usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC;
+
+        let source = texture(
+            "post source texture",
+            width,
+            height,
+            1,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+        );
+        let stages = [
+            texture("post stage texture", width, height, 1, stage_usage),
+            texture("post stage texture", width, height, 1, stage_usage),
+        ];
+
+        let bloom_width = (width / 2).max(1);
+        let bloom_height = (height / 2).max(1);
+        let bloom_levels = (bloom_width.min(bloom_height).ilog2() + 1).min(MAX_BLOOM_LEVELS);
+        let bloom = texture(
+            "bloom texture",
+            bloom_width,
+            bloom_height,
+            bloom_levels,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let placeholder = texture(
+            "bloom placeholder texture",
+            1,
+            1,
+            1,
+            wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        let view = |texture: &wgpu::Texture| texture.create_view(&Default::default());
+        let source_view = view(&source);
+        let stage_views = [view(&stages[0]), view(&stages[1])];
+        let bloom_views: Vec<_> = (0..bloom_levels)
+            .map(|level| {
+                bloom.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let placeholder_view = view(&placeholder);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post bind group layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<PassUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+        });
+
+        let bind_group = |input: &wgpu::TextureView, bloom: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post bind group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &uniform_buffer,
+                            offset: 0,
+                            size: wgpu::BufferSize::new(std::mem::size_of::<PassUniforms>() as u64),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(bloom),
+                    },
+                ],
+            })
+        };
+        let mut bind_groups = Vec::new();
+        for input in [&source_view, &stage_views[0], &stage_views[1]] {
+            bind_groups.push(bind_group(input, &placeholder_view));
+            bind_groups.push(bind_group(input, &bloom_views[0]));
+        }
+        for input in &bloom_views {
+            bind_groups.push(bind_group(input, &placeholder_view));
+        }
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+
+        Self {
+            pipeline: create_pipeline("post pipeline", wgpu::BlendState::REPLACE),
+            additive_pipeline: create_pipeline("additive post pipeline", additive),
+            uniform_buffer,
+            source,
+            stages,
+            stage_views,
+            bloom_views,
+            bind_groups,
+            source_view,
+            passes: Vec::new(),
+            output: Input::Source,
+        }
+    }
+
+    fn size(&self) -> iced::Size<u32> {
+        iced::Size::new(self.source.width(), self.source.height())
+    }
+
+    /// Plans the passes for the enabled effects, in order, and writes their uniforms. Only the
+    /// top left `render_size` pixels of the compute shader's output are read.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        effects: &[PostEffect],
+        render_size: iced::Size<u32>,
+    ) {
+        let size = self.size();
+        let full_size = [size.width as f32, size.height as f32];
+        let region = [
+            render_size.width as f32 / full_size[0],
+            render_size.height as f32 / full_size[1],
+        ];
+        let bloom_levels = self.bloom_views.len();
+        let bloom_size = |level: usize| {
+            let width = ((size.width / 2).max(1) >> level).max(1);
+            let height = ((size.height / 2).max(1) >> level).max(1);
+            [width as f32, height as f32]
+        };
+
+        self.passes.clear();
+        let mut slots = Vec::new();
+        let mut input = Input::Source;
+        let mut next_stage = 0;
+
+        for effect in effects.iter().filter(|effect| effect.enabled) {
+            let effect = effect.effect;
+            let is_bloom = matches!(effect, Effect::Bloom { .. });
+            let needed = if is_bloom { 2 * bloom_levels } else { 1 };
+            if self.passes.len() + needed > MAX_PASSES {
+                break;
+            }
+
+            let params = effect.values();
+            let mut push = |pass: Pass, output_size, kind| {
+                let region = if pass.input == Input::Source {
+                    region
+                } else {
+                    [1.0, 1.0]
+                };
+                self.passes.push(pass);
+                slots.push(PassUniforms {
+                    region,
+                    output_size,
+                    kind,
+                    _padding: [0; 3],
+                    params,
+                });
+            };
+
+            if is_bloom {
+                // Downsample to the bottom of the chain, then add each level back up into the one
+                // above it
+                for level in 0..bloom_levels {
+                    let (from, kind) = match level {
+                        0 => (input, BLOOM_PREFILTER),
+                        _ => (Input::Bloom(level - 1), BLOOM_DOWNSAMPLE),
+                    };
+                    let pass = Pass {
+                        input: from,
+                        output: Output::Bloom(level),
+                        composite: false,
+                        additive: false,
+                    };
+                    push(pass, bloom_size(level), kind);
+                }
+                for level in (0..bloom_levels - 1).rev() {
+                    let pass = Pass {
+                        input: Input::Bloom(level + 1),
+                        output: Output::Bloom(level),
+                        composite: false,
+                        additive: true,
+                    };
+                    push(pass, bloom_size(level), BLOOM_UPSAMPLE);
+                }
+            }
+
+            let pass = Pass {
+                input,
+                output: Output::Stage(next_stage),
+                composite: is_bloom,
+                additive: false,
+            };
+            push(pass, full_size, effect.index());
+
+            input = Input::Stage(next_stage);
+            next_stage = 1 - next_stage;
+        }
+
+        self.output = input;
+
+        for (index, slot) in slots.iter().enumerate() {
+            queue.write_buffer(
+                &self.uniform_buffer,
+                index as u64 * SLOT_SIZE,
+                bytes_of(slot),
+            );
+        }
+    }
+
+    /// Bind groups go source, then each stage, with a placeholder and then the bloom chain
+    /// bound for each. Bloom levels follow, only ever with the placeholder.
+    fn bind_group(&self, input: Input, composite: bool) -> &wgpu::BindGroup {
+        let index = match input {
+            Input::Source => composite as usize,
+            Input::Stage(stage) => 2 + 2 * stage + composite as usize,
+            Input::Bloom(level) => 6 + level,
+        };
+        &self.bind_groups[index]
+    }
+
+    /// Runs the stack on the compute shader's output.
+    pub fn process(&self, encoder: &mut wgpu::CommandEncoder, screen_texture: &wgpu::Texture) {
+        encoder.copy_texture_to_texture(
+            screen_texture.as_image_copy(),
+            self.source.as_image_copy(),
+            self.source.size(),
+        );
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let view = match pass.output {
+                Output::Stage(stage) => &self.stage_views[stage],
+                Output::Bloom(level) => &self.bloom_views[level],
+            };
+            let load = if pass.additive {
+                wgpu::LoadOp::Load
+            } else {
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("post pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_pipeline(if pass.additive {
+                &self.additive_pipeline
+            } else {
+                &self.pipeline
+            });
+            let offset = (index as u64 * SLOT_SIZE) as u32;
+            render_pass.set_bind_group(0, self.bind_group(pass.input, pass.composite), &[offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// Every texture the stack can finish in: the source, then each stage.
+    pub fn output_views(&self) -> [&wgpu::TextureView; 3] {
+        [
+            &self.source_view,
+            &self.stage_views[0],
+            &self.stage_views[1],
+        ]
+    }
+
+    /// Which of `output_views` holds the result once processed.
+    pub fn output(&self) -> usize {
+        match self.output {
+            Input::Stage(stage) => 1 + stage,
+            _ => 0,
+        }
+    }
+
+    /// The result, for reading back.
+    pub fn output_texture(&self) -> &wgpu::Texture {
+        match self.output {
+            Input::Stage(stage) => &self.stages[stage],
+            _ => &self.source,
+        }
+    }
+}
@@ -1,17 +1,45 @@
+use std::sync::Arc;
+
+use crate::environment::EnvironmentMap;
+use crate::lut::Look;
+use crate::post::PostEffect;
 use crate::shader::pipeline::ComputeShaderPipeline;
 use crate::shader::pipeline::RenderShaderPipeline;
-use iced::widget::shader::wgpu;
+use crate::shader::pipeline::Uniforms;
+use crate::tone_mapping::ToneMapping;
 use iced::{
     widget::shader::{self},
     Rectangle,
 };
 
 #[derive(Debug)]
-pub struct ShaderPrimitive {}
+pub struct ShaderPrimitive {
+    uniforms: Uniforms,
+    environment: Option<Arc<EnvironmentMap>>,
+    /// Fraction of the viewport's resolution to render at
+    render_scale: f32,
+    post: Vec<PostEffect>,
+    tone_mapping: ToneMapping,
+    look: Look,
+}
 
 impl ShaderPrimitive {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        uniforms: Uniforms,
+        environment: Option<Arc<EnvironmentMap>>,
+        render_scale: f32,
+        post: Vec<PostEffect>,
+        tone_mapping: ToneMapping,
+        look: Look,
+    ) -> Self {
+        Self {
+            uniforms,
+            environment,
+            render_scale,
+            post,
+            tone_mapping,
+            look,
+        }
     }
 }
 
@@ -21,30 +49,44 @@ impl shader::Primitive for ShaderPrimitive {
         format: shader::wgpu::TextureFormat,
         device: &shader::wgpu::Device,
         queue: &shader::wgpu::Queue,
-        _bounds: iced::Rectangle,
-        target_size: iced::Size<u32>,
-        _scale_factor: f32,
+        bounds: iced::Rectangle,
+        _target_size: iced::Size<u32>,
+        scale_factor: f32,
         storage: &mut shader::Storage,
     ) {
-        if !storage.has::<ComputeShaderPipeline>() {
-            storage.store(ComputeShaderPipeline::new(device, target_size));
+        // The widget only covers part of the window, so render at its size in physical pixels,
+        // snapped the same way as the viewport `render` is given
+        let bounds = (bounds * scale_factor).snap();
+        let size = iced::Size::new(bounds.width.max(1), bounds.height.max(1));
+
+        // Both pipelines own textures the size of the viewport, so start over on resize
+        let resized = storage
+            .get::<ComputeShaderPipeline>()
+            .is_some_and(|pipeline| pipeline.size() != size);
+
+        if !storage.has::<ComputeShaderPipeline>() || resized {
+            storage.store(ComputeShaderPipeline::new(device, queue, size));
         }
-        if !storage.has::<RenderShaderPipeline>() {
-            storage.store(RenderShaderPipeline::new(device, format, target_size));
+        if !storage.has::<RenderShaderPipeline>() || resized {
+            storage.store(RenderShaderPipeline::new(device, queue, format, size));
         }
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("compute command encoder"),
-        });
-
         let pipeline = storage.get_mut::<ComputeShaderPipeline>().unwrap();
 
-        // TODO: Add uniforms
-        //
-        // pipeline.update();
+        pipeline.set_environment(device, queue, self.environment.as_ref());
+        pipeline.update(queue, &self.uniforms, self.render_scale);
 
-        pipeline.dispatch(&mut encoder);
-        queue.submit(Some(encoder.finish()));
+        let render_size = pipeline.render_size();
+        let render_pipeline = storage.get_mut::<RenderShaderPipeline>().unwrap();
+        render_pipeline.set_lut(device, queue, self.look.lut.as_ref());
+        render_pipeline.update(
+            queue,
+            iced::Point::new(bounds.x as f32, bounds.y as f32),
+            render_size,
+            &self.post,
+            &self.tone_mapping,
+            &self.look,
+        );
 
         // Debug
         //
@@ -86,6 +128,6 @@ impl shader::Primitive for ShaderPrimitive {
         let compute_pipeline = storage.get::<ComputeShaderPipeline>().unwrap();
         let render_pipeline = storage.get::<RenderShaderPipeline>().unwrap();
         compute_pipeline.dispatch(encoder);
-        render_pipeline.render(encoder, target, &compute_pipeline.screen_texture);
+        render_pipeline.render(encoder, target, viewport, &compute_pipeline.screen_texture);
     }
 }
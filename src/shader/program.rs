@@ -1,42 +1,367 @@
-use iced::{event::Status, widget::shader};
+use std::time::Instant;
 
-use crate::{app::Message, shader::primitive::ShaderPrimitive};
+use glam::{Vec2, Vec3};
+use iced::{
+    event::Status,
+    keyboard, mouse,
+    widget::shader::{self, Event},
+    window,
+};
 
-#[derive(Default)]
-pub enum State {
-    #[default]
-    Idle,
+use crate::{
+    app::Message,
+    scene::Scene,
+    sdf,
+    shader::{pipeline::Uniforms, primitive::ShaderPrimitive},
+    stereo::{Stereo, View},
+};
+
+/// Keys for flying the camera, with the direction each moves in as right, up and forward.
+const FLY_KEYS: [(&str, Vec3); 6] = [
+    ("w", Vec3::new(0.0, 0.0, 1.0)),
+    ("s", Vec3::new(0.0, 0.0, -1.0)),
+    ("d", Vec3::new(1.0, 0.0, 0.0)),
+    ("a", Vec3::new(-1.0, 0.0, 0.0)),
+    ("e", Vec3::new(0.0, 1.0, 0.0)),
+    ("q", Vec3::new(0.0, -1.0, 0.0)),
+];
+
+/// Flying speed, in multiples of the distance to the nearest surface per second.
+const FLY_SPEED: f32 = 2.0;
+
+/// Radians turned per pixel the cursor moves while looking around.
+const LOOK_SPEED: f32 = 0.005;
+
+/// Lowest fraction of the viewport's resolution rendered while the view changes.
+const MIN_RENDER_SCALE: f32 = 0.25;
+
+pub struct State {
+    /// Uniforms the current accumulation started from
+    uniforms: Option<Uniforms>,
+    /// Frames accumulated so far
+    frames: u32,
+    /// Which of `FLY_KEYS` are held down
+    held: [bool; 6],
+    /// Shift speeds flying up
+    fast: bool,
+    /// When the camera last flew, to keep the speed independent of frame rate
+    last_flight: Option<Instant>,
+    /// Cursor position while looking around with the right mouse button
+    look_from: Option<iced::Point>,
+    /// Fraction of the viewport's resolution to render at
+    render_scale: f32,
+    /// When the last of an unbroken run of redraws happened, for timing frames
+    last_redraw: Option<Instant>,
 }
 
-pub struct ShaderProgram {}
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            uniforms: None,
+            frames: 0,
+            held: [false; 6],
+            fast: false,
+            last_flight: None,
+            look_from: None,
+            render_scale: 1.0,
+            last_redraw: None,
+        }
+    }
+}
 
-impl ShaderProgram {
-    pub fn new() -> Self {
-        Self {}
+pub struct ShaderProgram<'a> {
+    scene: &'a Scene,
+}
+
+impl<'a> ShaderProgram<'a> {
+    pub fn new(scene: &'a Scene) -> Self {
+        Self { scene }
+    }
+
+    /// Raymarches the pixel under `position`, a point in the window, on the CPU, returning the
+    /// focus distance that would bring it into focus.
+    fn pick_focus(&self, position: iced::Point, bounds: iced::Rectangle) -> Option<f32> {
+        let camera = &self.scene.camera;
+        let stereo = &self.scene.stereo;
+
+        let view = view_at(stereo, position, bounds);
+        let aspect_ratio = view.size.x / view.size.y;
+        let (origin, direction) = camera.ray(view.uv, aspect_ratio)?;
+        let (eye_origin, eye_direction) = stereo.eye_ray(camera, origin, direction, view.eye);
+        let pixel = camera.pixel_size(view.size.y, aspect_ratio);
+
+        let distance = sdf::raymarch(
+            self.scene.fractal,
+            &self.scene.fractal_parameters,
+            eye_origin,
+            eye_direction,
+            &self.scene.marcher,
+            pixel,
+        )?;
+
+        // Focus distance is measured along the view direction for planar projections
+        if camera.projection.is_planar() {
+            Some(distance * direction.dot(camera.direction.normalize()))
+        } else {
+            Some(distance)
+        }
+    }
+
+    /// Adjusts the render scale towards holding the target frame rate, given how long the last
+    /// frame took.
+    fn adjust_render_scale(&self, scale: f32, frame_time: f32) -> f32 {
+        let target_frame_time = 1.0 / self.scene.target_frame_rate.max(1.0);
+
+        // Cost goes with the number of pixels, so with the square of the scale
+        let ideal = scale * (target_frame_time / frame_time).sqrt();
+
+        // Only go part of the way each frame, so one slow frame doesn't make the view flicker
+        (scale + (ideal - scale) * 0.5).clamp(MIN_RENDER_SCALE, 1.0)
+    }
+
+    /// Moves the camera along the held fly keys, for `elapsed` seconds.
+    fn fly(&self, state: &State, elapsed: f32) -> Option<Message> {
+        let camera = &self.scene.camera;
+
+        let input: Vec3 = FLY_KEYS
+            .iter()
+            .zip(state.held)
+            .filter_map(|((_, axis), held)| held.then_some(*axis))
+            .sum();
+        if input == Vec3::ZERO {
+            return None;
+        }
+
+        // Slow down near surfaces, so flying into fine detail stays controllable
+        let distance = sdf::sdf(
+            self.scene.fractal,
+            &self.scene.fractal_parameters,
+            camera.position,
+        )
+        .abs()
+        .max(self.scene.marcher.min_distance);
+        let speed = distance * FLY_SPEED * if state.fast { 4.0 } else { 1.0 };
+
+        let (forward, right, up) = camera.basis();
+        let velocity = input.x * right + input.y * up + input.z * forward;
+
+        Some(Message::CameraMoved {
+            position: camera.position + velocity * speed * elapsed,
+            direction: camera.direction,
+        })
+    }
+
+    /// Turns the camera by a cursor movement of `delta` pixels.
+    fn look(&self, delta: iced::Vector) -> Message {
+        let camera = &self.scene.camera;
+        let direction = camera.direction.normalize();
+
+        let yaw = direction.y.atan2(direction.x) - delta.x * LOOK_SPEED;
+        // Stop short of straight up and down, where the camera basis degenerates
+        let pitch = (direction.z.asin() - delta.y * LOOK_SPEED).clamp(-1.5, 1.5);
+
+        Message::CameraMoved {
+            position: camera.position,
+            direction: Vec3::new(
+                pitch.cos() * yaw.cos(),
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+            ),
+        }
     }
 }
 
-impl shader::Program<Message> for ShaderProgram {
+impl shader::Program<Message> for ShaderProgram<'_> {
     type State = State;
     type Primitive = ShaderPrimitive;
 
     fn draw(
         &self,
         state: &Self::State,
-        cursor: iced::advanced::mouse::Cursor,
-        bounds: iced::Rectangle,
+        _cursor: mouse::Cursor,
+        _bounds: iced::Rectangle,
     ) -> Self::Primitive {
-        Self::Primitive::new()
+        Self::Primitive::new(
+            Uniforms::new(self.scene),
+            self.scene.environment.map.clone(),
+            state.render_scale,
+            self.scene.post.clone(),
+            self.scene.display_tone_mapping(),
+            self.scene.display_look(),
+        )
     }
 
     fn update(
         &self,
-        _state: &mut Self::State,
-        _event: shader::Event,
-        _bounds: iced::Rectangle,
-        _cursor: iced::advanced::mouse::Cursor,
-        _shell: &mut iced::advanced::Shell<'_, Message>,
+        state: &mut Self::State,
+        event: Event,
+        bounds: iced::Rectangle,
+        cursor: mouse::Cursor,
+        shell: &mut iced::advanced::Shell<'_, Message>,
     ) -> (Status, Option<Message>) {
-        (Status::Ignored, None)
+        match event {
+            Event::RedrawRequested(now) => {
+                let elapsed = state
+                    .last_flight
+                    .map_or(0.0, |last| (now - last).as_secs_f32());
+                let flight = self.fly(state, elapsed);
+                state.last_flight = state.held.contains(&true).then_some(now);
+
+                // Keep redrawing until enough samples have been accumulated
+                let uniforms = Uniforms::new(self.scene);
+                let changed = state.uniforms != Some(uniforms);
+                if changed {
+                    state.uniforms = Some(uniforms);
+                    state.frames = 0;
+                }
+
+                // Drop resolution to keep up while the view changes, and go back to full
+                // resolution as soon as it stops
+                let frame_time = state.last_redraw.map(|last| (now - last).as_secs_f32());
+                state.render_scale = match frame_time {
+                    Some(frame_time) if changed => {
+                        self.adjust_render_scale(state.render_scale, frame_time)
+                    }
+                    _ if changed => state.render_scale,
+                    _ => 1.0,
+                };
+
+                let redraw = state.last_flight.is_some() || state.frames < self.scene.samples;
+                if redraw {
+                    state.frames += 1;
+                    shell.request_redraw(window::RedrawRequest::NextFrame);
+                }
+                state.last_redraw = redraw.then_some(now);
+
+                (Status::Ignored, flight)
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key: keyboard::Key::Character(key),
+                ..
+            }) => {
+                // Keys reach every widget, so only fly while pointing at the viewport rather
+                // than while typing into a text field
+                let Some(index) = fly_key(&key).filter(|_| cursor.is_over(bounds)) else {
+                    return (Status::Ignored, None);
+                };
+
+                // One flight is one step in the history, however long it lasts
+                let took_off = (!state.held.contains(&true)).then_some(Message::EditStarted);
+                state.held[index] = true;
+                shell.request_redraw(window::RedrawRequest::NextFrame);
+                (Status::Captured, took_off)
+            }
+            Event::Keyboard(keyboard::Event::KeyReleased {
+                key: keyboard::Key::Character(key),
+                ..
+            }) => {
+                // Released keys stop the camera wherever the cursor has gone since
+                let Some(index) = fly_key(&key).filter(|&index| state.held[index]) else {
+                    return (Status::Ignored, None);
+                };
+
+                state.held[index] = false;
+                let landed = (!state.held.contains(&true)).then_some(Message::EditFinished);
+                (Status::Captured, landed)
+            }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.fast = modifiers.shift();
+                (Status::Ignored, None)
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                if !cursor.is_over(bounds) {
+                    return (Status::Ignored, None);
+                }
+
+                // Each drag is its own step in the history, rather than merging with whatever
+                // camera edit came just before or splitting up when it pauses
+                state.look_from = cursor.position();
+                (Status::Captured, Some(Message::EditStarted))
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Right)) => {
+                if state.look_from.take().is_none() {
+                    return (Status::Ignored, None);
+                }
+
+                (Status::Captured, Some(Message::EditFinished))
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let Some(from) = state.look_from.replace(position) else {
+                    return (Status::Ignored, None);
+                };
+
+                (Status::Captured, Some(self.look(position - from)))
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return (Status::Ignored, None);
+                };
+
+                let message = self
+                    .pick_focus(position, bounds)
+                    .map(Message::FocusDistanceChanged);
+                (Status::Captured, message)
+            }
+            _ => (Status::Ignored, None),
+        }
+    }
+}
+
+fn fly_key(key: &str) -> Option<usize> {
+    FLY_KEYS
+        .iter()
+        .position(|(fly_key, _)| fly_key.eq_ignore_ascii_case(key))
+}
+
+/// The view under `position`, a point in the window, for an image rendered to fill `bounds`.
+fn view_at(stereo: &Stereo, position: iced::Point, bounds: iced::Rectangle) -> View {
+    stereo.view(
+        Vec2::new(position.x - bounds.x, position.y - bounds.y),
+        Vec2::new(bounds.width, bounds.height),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stereo::StereoMode;
+
+    use super::*;
+
+    /// A viewport below a toolbar and beside a panel, like the app's.
+    const BOUNDS: iced::Rectangle = iced::Rectangle {
+        x: 300.0,
+        y: 40.0,
+        width: 800.0,
+        height: 400.0,
+    };
+
+    fn uv(stereo: &Stereo, x: f32, y: f32) -> Vec2 {
+        view_at(stereo, iced::Point::new(x, y), BOUNDS).uv
+    }
+
+    #[test]
+    fn maps_the_bounds_to_the_view() {
+        let stereo = Stereo::default();
+        assert_eq!(uv(&stereo, 300.0, 40.0), Vec2::new(-0.5, 0.5));
+        assert_eq!(uv(&stereo, 700.0, 240.0), Vec2::ZERO);
+        assert_eq!(uv(&stereo, 1100.0, 440.0), Vec2::new(0.5, -0.5));
+
+        let view = view_at(&stereo, iced::Point::new(700.0, 240.0), BOUNDS);
+        assert_eq!(view.size, Vec2::new(800.0, 400.0));
+        assert_eq!(view.eye, 0.0);
+    }
+
+    #[test]
+    fn maps_each_half_of_a_stereo_pair_to_its_eye() {
+        let stereo = Stereo {
+            mode: StereoMode::SideBySide,
+            ..Stereo::default()
+        };
+
+        let left = view_at(&stereo, iced::Point::new(500.0, 240.0), BOUNDS);
+        assert_eq!((left.uv, left.eye), (Vec2::ZERO, -1.0));
+        let right = view_at(&stereo, iced::Point::new(900.0, 240.0), BOUNDS);
+        assert_eq!((right.uv, right.eye), (Vec2::ZERO, 1.0));
+        assert_eq!(right.size, Vec2::new(400.0, 400.0));
     }
 }
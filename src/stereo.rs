@@ -0,0 +1,132 @@
+use std::fmt;
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+
+/// How the two eyes of a stereo pair are laid out in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StereoMode {
+    #[default]
+    Off,
+    /// Left eye in the left half, right eye in the right
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom
+    OverUnder,
+    /// Red from the left eye and cyan from the right, for red/cyan glasses
+    Anaglyph,
+}
+
+impl StereoMode {
+    pub const ALL: [Self; 4] = [Self::Off, Self::SideBySide, Self::OverUnder, Self::Anaglyph];
+
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::SideBySide => 1,
+            Self::OverUnder => 2,
+            Self::Anaglyph => 3,
+        }
+    }
+}
+
+impl fmt::Display for StereoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Off => "Mono",
+            Self::SideBySide => "Side by side",
+            Self::OverUnder => "Over under",
+            Self::Anaglyph => "Anaglyph",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A pair of cameras either side of the scene's camera, converging on a plane in front of it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    /// Distance between the eyes, in scene units
+    pub interocular_distance: f32,
+    /// Distance to the plane with zero parallax, along the view direction. Anything nearer
+    /// appears in front of the screen
+    pub convergence_distance: f32,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            mode: StereoMode::default(),
+            interocular_distance: 0.1,
+            convergence_distance: 4.0,
+        }
+    }
+}
+
+/// One eye's part of the image.
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    /// Position within the view, in [-0.5, 0.5] with y pointing up
+    pub uv: Vec2,
+    /// Size of the view in pixels
+    pub size: Vec2,
+    /// -1 for the left eye, 1 for the right and 0 for the centre
+    pub eye: f32,
+}
+
+impl Stereo {
+    /// Finds the view under `position`, in pixels from the top left of an image of `size`.
+    /// Mirrors the split in `main_image` in `shader.wgsl`. Anaglyphs overlay both eyes, so they
+    /// map to the centre.
+    pub fn view(&self, position: Vec2, size: Vec2) -> View {
+        let (view_size, origin, eye) = match self.mode {
+            StereoMode::SideBySide => {
+                let half = size.x * 0.5;
+                if position.x < half {
+                    (Vec2::new(half, size.y), Vec2::ZERO, -1.0)
+                } else {
+                    (Vec2::new(half, size.y), Vec2::new(half, 0.0), 1.0)
+                }
+            }
+            StereoMode::OverUnder => {
+                let half = size.y * 0.5;
+                if position.y < half {
+                    (Vec2::new(size.x, half), Vec2::ZERO, -1.0)
+                } else {
+                    (Vec2::new(size.x, half), Vec2::new(0.0, half), 1.0)
+                }
+            }
+            StereoMode::Off | StereoMode::Anaglyph => (size, Vec2::ZERO, 0.0),
+        };
+
+        let local = (position - origin) / view_size;
+        View {
+            uv: Vec2::new(local.x - 0.5, 0.5 - local.y),
+            size: view_size,
+            eye,
+        }
+    }
+
+    /// Turns the camera's pinhole ray into one from `eye`, aimed at the same point on the
+    /// convergence plane. Mirrors `sample_view` in `shader.wgsl`.
+    pub fn eye_ray(
+        &self,
+        camera: &Camera,
+        origin: Vec3,
+        direction: Vec3,
+        eye: f32,
+    ) -> (Vec3, Vec3) {
+        let (forward, right, _) = camera.basis();
+        let plane_scale = if camera.projection.is_planar() {
+            1.0 / direction.dot(forward)
+        } else {
+            1.0
+        };
+
+        let convergence_point = origin + direction * self.convergence_distance * plane_scale;
+        let eye_origin = origin + right * eye * self.interocular_distance * 0.5;
+        (eye_origin, (convergence_point - eye_origin).normalize())
+    }
+}
@@ -0,0 +1,519 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scene::Scene;
+
+/// A timeline read from a file that can't be played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A track without any keys, which has no value to give
+    EmptyTrack(Parameter),
+    /// Frame rates below or at zero have no time between frames
+    FrameRate(f32),
+    Duration(f32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyTrack(parameter) => write!(f, "the {parameter} track has no keys"),
+            Self::FrameRate(rate) => write!(f, "frame rate {rate} isn't above zero"),
+            Self::Duration(duration) => write!(f, "duration {duration} isn't above zero"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A numeric scene setting that can be keyframed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Parameter {
+    CameraX,
+    CameraY,
+    CameraZ,
+    DirectionX,
+    DirectionY,
+    DirectionZ,
+    Fov,
+    Aperture,
+    FocusDistance,
+    OrthographicSize,
+    Exposure,
+    FogDensity,
+    FogHeight,
+    GlowIntensity,
+    Scattering,
+    LutStrength,
+    LightX,
+    LightY,
+    LightZ,
+    SierpinskiScale,
+    MandelboxScale,
+    MandelboxFoldLimit,
+    MandelboxMinRadius,
+    MandelboxMaxRadius,
+}
+
+impl Parameter {
+    pub const ALL: [Self; 24] = [
+        Self::CameraX,
+        Self::CameraY,
+        Self::CameraZ,
+        Self::DirectionX,
+        Self::DirectionY,
+        Self::DirectionZ,
+        Self::Fov,
+        Self::Aperture,
+        Self::FocusDistance,
+        Self::OrthographicSize,
+        Self::Exposure,
+        Self::FogDensity,
+        Self::FogHeight,
+        Self::GlowIntensity,
+        Self::Scattering,
+        Self::LutStrength,
+        Self::LightX,
+        Self::LightY,
+        Self::LightZ,
+        Self::SierpinskiScale,
+        Self::MandelboxScale,
+        Self::MandelboxFoldLimit,
+        Self::MandelboxMinRadius,
+        Self::MandelboxMaxRadius,
+    ];
+
+    /// Everything that makes up the camera's view, keyed together for fly-throughs.
+    pub const CAMERA: [Self; 8] = [
+        Self::CameraX,
+        Self::CameraY,
+        Self::CameraZ,
+        Self::DirectionX,
+        Self::DirectionY,
+        Self::DirectionZ,
+        Self::Fov,
+        Self::FocusDistance,
+    ];
+
+    pub fn get(&self, scene: &Scene) -> f32 {
+        match self {
+            Self::CameraX => scene.camera.position.x,
+            Self::CameraY => scene.camera.position.y,
+            Self::CameraZ => scene.camera.position.z,
+            Self::DirectionX => scene.camera.direction.x,
+            Self::DirectionY => scene.camera.direction.y,
+            Self::DirectionZ => scene.camera.direction.z,
+            Self::Fov => scene.camera.fov,
+            Self::Aperture => scene.camera.aperture,
+            Self::FocusDistance => scene.camera.focus_distance,
+            Self::OrthographicSize => scene.camera.orthographic_size,
+            Self::Exposure => scene.tone_mapping.exposure,
+            Self::FogDensity => scene.atmosphere.fog_density,
+            Self::FogHeight => scene.atmosphere.fog_height,
+            Self::GlowIntensity => scene.atmosphere.glow_intensity,
+            Self::Scattering => scene.atmosphere.scattering,
+            Self::LutStrength => scene.look.strength,
+            Self::LightX => scene.light.position.x,
+            Self::LightY => scene.light.position.y,
+            Self::LightZ => scene.light.position.z,
+            Self::SierpinskiScale => scene.fractal_parameters.sierpinski.scale,
+            Self::MandelboxScale => scene.fractal_parameters.mandelbox.scale,
+            Self::MandelboxFoldLimit => scene.fractal_parameters.mandelbox.fold_limit,
+            Self::MandelboxMinRadius => scene.fractal_parameters.mandelbox.min_radius,
+            Self::MandelboxMaxRadius => scene.fractal_parameters.mandelbox.max_radius,
+        }
+    }
+
+    pub fn set(&self, scene: &mut Scene, value: f32) {
+        let field = match self {
+            Self::CameraX => &mut scene.camera.position.x,
+            Self::CameraY => &mut scene.camera.position.y,
+            Self::CameraZ => &mut scene.camera.position.z,
+            Self::DirectionX => &mut scene.camera.direction.x,
+            Self::DirectionY => &mut scene.camera.direction.y,
+            Self::DirectionZ => &mut scene.camera.direction.z,
+            Self::Fov => &mut scene.camera.fov,
+            Self::Aperture => &mut scene.camera.aperture,
+            Self::FocusDistance => &mut scene.camera.focus_distance,
+            Self::OrthographicSize => &mut scene.camera.orthographic_size,
+            Self::Exposure => &mut scene.tone_mapping.exposure,
+            Self::FogDensity => &mut scene.atmosphere.fog_density,
+            Self::FogHeight => &mut scene.atmosphere.fog_height,
+            Self::GlowIntensity => &mut scene.atmosphere.glow_intensity,
+            Self::Scattering => &mut scene.atmosphere.scattering,
+            Self::LutStrength => &mut scene.look.strength,
+            Self::LightX => &mut scene.light.position.x,
+            Self::LightY => &mut scene.light.position.y,
+            Self::LightZ => &mut scene.light.position.z,
+            Self::SierpinskiScale => &mut scene.fractal_parameters.sierpinski.scale,
+            Self::MandelboxScale => &mut scene.fractal_parameters.mandelbox.scale,
+            Self::MandelboxFoldLimit => &mut scene.fractal_parameters.mandelbox.fold_limit,
+            Self::MandelboxMinRadius => &mut scene.fractal_parameters.mandelbox.min_radius,
+            Self::MandelboxMaxRadius => &mut scene.fractal_parameters.mandelbox.max_radius,
+        };
+        *field = value;
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::CameraX => "Camera X",
+            Self::CameraY => "Camera Y",
+            Self::CameraZ => "Camera Z",
+            Self::DirectionX => "Direction X",
+            Self::DirectionY => "Direction Y",
+            Self::DirectionZ => "Direction Z",
+            Self::Fov => "Field of view",
+            Self::Aperture => "Aperture",
+            Self::FocusDistance => "Focus distance",
+            Self::OrthographicSize => "Orthographic size",
+            Self::Exposure => "Exposure",
+            Self::FogDensity => "Fog density",
+            Self::FogHeight => "Fog height",
+            Self::GlowIntensity => "Glow intensity",
+            Self::Scattering => "Scattering",
+            Self::LutStrength => "LUT strength",
+            Self::LightX => "Light X",
+            Self::LightY => "Light Y",
+            Self::LightZ => "Light Z",
+            Self::SierpinskiScale => "Sierpinski scale",
+            Self::MandelboxScale => "Mandelbox scale",
+            Self::MandelboxFoldLimit => "Mandelbox fold limit",
+            Self::MandelboxMinRadius => "Mandelbox min radius",
+            Self::MandelboxMaxRadius => "Mandelbox max radius",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Curve from a keyframe to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    Linear,
+    /// Passes smoothly through every key, with the slope at each set by its neighbours
+    #[default]
+    CatmullRom,
+    /// Cubic Bezier with flat handles, easing out of one key and into the next
+    Bezier,
+}
+
+impl Interpolation {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::CatmullRom, Self::Bezier];
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Linear => "Linear",
+            Self::CatmullRom => "Catmull-Rom",
+            Self::Bezier => "Bezier",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Seconds from the start of the timeline
+    pub time: f32,
+    pub value: f32,
+    pub interpolation: Interpolation,
+}
+
+/// The keyframes of one parameter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub parameter: Parameter,
+    /// Sorted by time, never empty
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Track {
+    /// Value of the curve at `time`, holding the first and last keys outside of them.
+    pub fn sample(&self, time: f32) -> f32 {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return keys[0].value;
+        }
+        if next == keys.len() {
+            return keys[next - 1].value;
+        }
+
+        let (from, to) = (&keys[next - 1], &keys[next]);
+        let duration = to.time - from.time;
+        let t = (time - from.time) / duration;
+
+        match from.interpolation {
+            Interpolation::Linear => from.value + (to.value - from.value) * t,
+            Interpolation::CatmullRom => {
+                let slope = |index: usize| {
+                    let before = &keys[index.saturating_sub(1)];
+                    let after = &keys[(index + 1).min(keys.len() - 1)];
+                    (after.value - before.value) / (after.time - before.time)
+                };
+                hermite(
+                    from.value,
+                    slope(next - 1) * duration,
+                    to.value,
+                    slope(next) * duration,
+                    t,
+                )
+            }
+            Interpolation::Bezier => hermite(from.value, 0.0, to.value, 0.0, t),
+        }
+    }
+}
+
+impl Track {
+    /// Puts keys read from a file in order, keeping the last of any at the same time.
+    fn tidy(&mut self) {
+        self.keyframes
+            .sort_by(|first, second| first.time.total_cmp(&second.time));
+        self.keyframes.dedup_by(|later, earlier| {
+            let duplicate = later.time == earlier.time;
+            if duplicate {
+                *earlier = *later;
+            }
+            duplicate
+        });
+    }
+}
+
+/// Whether `value` is a finite number above zero, as frame rates and durations must be.
+pub fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+/// Cubic Hermite curve between `from` and `to` with the given tangents, for `t` in [0, 1].
+fn hermite(from: f32, from_tangent: f32, to: f32, to_tangent: f32, t: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * from
+        + (t3 - 2.0 * t2 + t) * from_tangent
+        + (-2.0 * t3 + 3.0 * t2) * to
+        + (t3 - t2) * to_tangent
+}
+
+/// Keyframed animation of scene parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeline {
+    /// Length in seconds
+    pub duration: f32,
+    pub frame_rate: f32,
+    /// Fraction of each frame the shutter is open for when rendering, in degrees. 360 blurs
+    /// motion over the whole frame, 0 turns motion blur off
+    pub shutter_angle: f32,
+    pub tracks: Vec<Track>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            duration: 10.0,
+            frame_rate: 30.0,
+            shutter_angle: 180.0,
+            tracks: Vec::new(),
+        }
+    }
+}
+
+impl Timeline {
+    /// Makes a timeline read from a file safe to play, sorting the keys of each track and
+    /// dropping ones at the same time as another. Fails on a track without any keys, or a frame
+    /// rate or duration that isn't a positive number.
+    pub fn tidy(&mut self) -> Result<(), Error> {
+        if !is_positive(self.frame_rate) {
+            return Err(Error::FrameRate(self.frame_rate));
+        }
+        if !is_positive(self.duration) {
+            return Err(Error::Duration(self.duration));
+        }
+
+        for track in &mut self.tracks {
+            if track.keyframes.is_empty() {
+                return Err(Error::EmptyTrack(track.parameter));
+            }
+            track.tidy();
+        }
+        Ok(())
+    }
+
+    pub fn track(&self, parameter: Parameter) -> Option<&Track> {
+        self.tracks
+            .iter()
+            .find(|track| track.parameter == parameter)
+    }
+
+    /// Keys `value` at `time`, replacing a key already there.
+    pub fn set_key(&mut self, parameter: Parameter, time: f32, value: f32) {
+        let Some(track) = self
+            .tracks
+            .iter_mut()
+            .find(|track| track.parameter == parameter)
+        else {
+            self.tracks.push(Track {
+                parameter,
+                keyframes: vec![Keyframe {
+                    time,
+                    value,
+                    interpolation: Interpolation::default(),
+                }],
+            });
+            return;
+        };
+
+        let index = track.keyframes.partition_point(|key| key.time < time);
+        match track.keyframes.get_mut(index) {
+            Some(key) if (key.time - time).abs() < 1e-4 => key.value = value,
+            _ => {
+                // Carry on with the curve the key is splitting
+                let interpolation = index
+                    .checked_sub(1)
+                    .map_or(Interpolation::default(), |before| {
+                        track.keyframes[before].interpolation
+                    });
+                track.keyframes.insert(
+                    index,
+                    Keyframe {
+                        time,
+                        value,
+                        interpolation,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Removes a key, and the whole track with its last one.
+    pub fn remove_key(&mut self, parameter: Parameter, index: usize) {
+        if let Some(position) = self
+            .tracks
+            .iter()
+            .position(|track| track.parameter == parameter)
+        {
+            let track = &mut self.tracks[position];
+            track.keyframes.remove(index);
+            if track.keyframes.is_empty() {
+                self.tracks.remove(position);
+            }
+        }
+    }
+
+    pub fn set_interpolation(
+        &mut self,
+        parameter: Parameter,
+        index: usize,
+        interpolation: Interpolation,
+    ) {
+        if let Some(track) = self
+            .tracks
+            .iter_mut()
+            .find(|track| track.parameter == parameter)
+        {
+            track.keyframes[index].interpolation = interpolation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(keys: &[(f32, f32)], interpolation: Interpolation) -> Track {
+        Track {
+            parameter: Parameter::Fov,
+            keyframes: keys
+                .iter()
+                .map(|&(time, value)| Keyframe {
+                    time,
+                    value,
+                    interpolation,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn holds_the_end_keys_outside_of_them() {
+        let track = track(&[(1.0, 10.0), (2.0, 20.0)], Interpolation::CatmullRom);
+        assert_eq!(track.sample(0.0), 10.0);
+        assert_eq!(track.sample(1.0), 10.0);
+        assert_eq!(track.sample(2.0), 20.0);
+        assert_eq!(track.sample(5.0), 20.0);
+    }
+
+    #[test]
+    fn interpolates_between_keys() {
+        let linear = track(&[(0.0, 0.0), (2.0, 10.0)], Interpolation::Linear);
+        assert_eq!(linear.sample(0.5), 2.5);
+
+        // Flat handles ease out and in, crossing halfway at the midpoint
+        let bezier = track(&[(0.0, 0.0), (2.0, 10.0)], Interpolation::Bezier);
+        assert_eq!(bezier.sample(1.0), 5.0);
+        assert!(bezier.sample(0.5) < 2.5);
+
+        // A straight line of keys stays straight
+        let catmull_rom = track(
+            &[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)],
+            Interpolation::CatmullRom,
+        );
+        assert!((catmull_rom.sample(0.25) - 0.25).abs() < 1e-6);
+        assert!((catmull_rom.sample(1.5) - 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn holds_a_single_key_everywhere() {
+        let track = track(&[(1.0, 7.0)], Interpolation::CatmullRom);
+        for time in [-1.0, 1.0, 3.0] {
+            assert_eq!(track.sample(time), 7.0);
+        }
+    }
+
+    #[test]
+    fn tidies_keys_read_from_a_file() {
+        let mut timeline = Timeline {
+            tracks: vec![track(
+                &[(2.0, 20.0), (0.0, 0.0), (2.0, 30.0)],
+                Interpolation::Linear,
+            )],
+            ..Timeline::default()
+        };
+        timeline.tidy().unwrap();
+
+        let track = &timeline.tracks[0];
+        let times: Vec<_> = track.keyframes.iter().map(|key| key.time).collect();
+        assert_eq!(times, [0.0, 2.0]);
+        assert_eq!(track.sample(2.0), 30.0);
+        assert!(track.sample(1.0).is_finite());
+    }
+
+    #[test]
+    fn rejects_empty_tracks() {
+        let mut timeline = Timeline {
+            tracks: vec![track(&[], Interpolation::Linear)],
+            ..Timeline::default()
+        };
+        assert_eq!(timeline.tidy(), Err(Error::EmptyTrack(Parameter::Fov)));
+    }
+
+    #[test]
+    fn rejects_frame_rates_and_durations_that_arent_positive() {
+        for frame_rate in [0.0, -24.0, f32::INFINITY] {
+            let mut timeline = Timeline {
+                frame_rate,
+                ..Timeline::default()
+            };
+            assert_eq!(timeline.tidy(), Err(Error::FrameRate(frame_rate)));
+        }
+
+        let mut timeline = Timeline {
+            duration: 0.0,
+            ..Timeline::default()
+        };
+        assert_eq!(timeline.tidy(), Err(Error::Duration(0.0)));
+        assert!(Timeline::default().tidy().is_ok());
+    }
+}
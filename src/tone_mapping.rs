@@ -0,0 +1,134 @@
+use std::fmt;
+
+use glam::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Curve squeezing linear HDR values into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ToneMapper {
+    /// Clips anything above 1
+    None,
+    /// `x / (1 + x)` per channel
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    #[default]
+    Aces,
+    /// Desaturates highlights towards white instead of skewing their hue
+    Agx,
+}
+
+impl ToneMapper {
+    pub const ALL: [Self; 4] = [Self::None, Self::Reinhard, Self::Aces, Self::Agx];
+
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Reinhard => 1,
+            Self::Aces => 2,
+            Self::Agx => 3,
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::None => "None",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES",
+            Self::Agx => "AgX",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How the linear render is turned into an image for display. Mirrors `render.wgsl`, which does
+/// the same for the viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneMapping {
+    pub mapper: ToneMapper,
+    /// Stops of exposure applied before tone mapping
+    pub exposure: f32,
+}
+
+impl ToneMapping {
+    /// Leaves values in [0, 1] untouched, for debug views.
+    pub fn neutral() -> Self {
+        Self {
+            mapper: ToneMapper::None,
+            exposure: 0.0,
+        }
+    }
+
+    /// Maps a linear color to linear display values in [0, 1].
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let color = color * self.exposure.exp2();
+
+        match self.mapper {
+            ToneMapper::None => color,
+            ToneMapper::Reinhard => color / (color + 1.0),
+            ToneMapper::Aces => aces(color),
+            ToneMapper::Agx => agx(color),
+        }
+        .clamp(Vec3::ZERO, Vec3::ONE)
+    }
+}
+
+fn aces(x: Vec3) -> Vec3 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Minimal AgX, after https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx(color: Vec3) -> Vec3 {
+    const INSET: Mat3 = Mat3::from_cols_array(&[
+        0.8424791, 0.04232824, 0.04237565, 0.0784336, 0.8784686, 0.0784336, 0.07922375, 0.07916613,
+        0.879143,
+    ]);
+    const OUTSET: Mat3 = Mat3::from_cols_array(&[
+        1.196879,
+        -0.05289685,
+        -0.05297164,
+        -0.09802088,
+        1.151903,
+        -0.09804345,
+        -0.09902974,
+        -0.09896118,
+        1.151074,
+    ]);
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let log = (INSET * color.max(Vec3::splat(1e-10)))
+        .to_array()
+        .map(|c| c.log2().clamp(MIN_EV, MAX_EV));
+    let x = (Vec3::from_array(log) - MIN_EV) / (MAX_EV - MIN_EV);
+
+    // Polynomial fit of the default contrast curve
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve =
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232;
+
+    // The curve's output is display encoded, take it back to linear
+    (OUTSET * curve).max(Vec3::ZERO).powf(2.2)
+}
+
+/// Encodes a linear value in [0, 1] with the sRGB transfer function.
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of [`srgb_encode`].
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
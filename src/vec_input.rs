@@ -0,0 +1,355 @@
+// Rows of number fields for editing vectors, with the axis labels doubling as handles to drag the
+// values with.
+
+use std::ops::RangeInclusive;
+
+use iced::{
+    advanced::{
+        layout, mouse, renderer,
+        widget::{tree, Tree},
+        Clipboard, Layout, Shell, Widget,
+    },
+    event, keyboard,
+    widget::{text, text_input, Column, Row},
+    Alignment, Element, Event, Length, Rectangle, Renderer, Size, Theme,
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Text was typed into the field for an axis
+    Typed(usize, String),
+    /// An axis label was dragged to a new value
+    Scrubbed(usize, f32),
+    /// A field was submitted or a drag let go of, ending the edit
+    Finished,
+}
+
+pub enum Action<const N: usize> {
+    Change([f32; N]),
+    Finish,
+    None,
+}
+
+/// Text typed into a field that hasn't been formatted back from the value, so numbers can be
+/// typed through states like `-` or `1.` that don't parse or read back differently.
+#[derive(Debug, Clone)]
+struct Draft {
+    axis: usize,
+    text: String,
+    /// Value of the axis when the text was typed. The draft is dropped once the value changes
+    /// some other way
+    value: f32,
+}
+
+/// A row of number fields editing an `N` component vector, like a `Vec2`, `Vec3` or
+/// `Vec4`. Typed values are only passed on once they parse, clamped to the range, and
+/// dragging an axis label sideways scrubs its value by `step` per pixel, or a tenth of that with
+/// shift held.
+#[derive(Debug, Clone)]
+pub struct VecInput<const N: usize> {
+    title: Option<&'static str>,
+    labels: [&'static str; N],
+    range: RangeInclusive<f32>,
+    step: f32,
+    /// Shown after the fields
+    unit: &'static str,
+    draft: Option<Draft>,
+}
+
+impl<const N: usize> Default for VecInput<N> {
+    fn default() -> Self {
+        Self {
+            title: None,
+            labels: std::array::from_fn(|axis| {
+                ["X", "Y", "Z", "W"].get(axis).copied().unwrap_or("")
+            }),
+            range: f32::MIN..=f32::MAX,
+            step: 0.01,
+            unit: "",
+            draft: None,
+        }
+    }
+}
+
+impl<const N: usize> VecInput<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(self, title: &'static str) -> Self {
+        Self {
+            title: Some(title),
+            ..self
+        }
+    }
+
+    /// Names of the axes, `X` to `W` by default. A single value is best labelled with its name
+    /// instead of a title.
+    pub fn labels(self, labels: [&'static str; N]) -> Self {
+        Self { labels, ..self }
+    }
+
+    /// Limits every axis to `range`. Values typed outside of it are clamped to it and drags stop
+    /// at its ends. The clamped value shows once the field is submitted.
+    pub fn range(self, range: RangeInclusive<f32>) -> Self {
+        Self { range, ..self }
+    }
+
+    /// Change per pixel dragged.
+    pub fn step(self, step: f32) -> Self {
+        Self { step, ..self }
+    }
+
+    pub fn unit(self, unit: &'static str) -> Self {
+        Self { unit, ..self }
+    }
+}
+
+impl<const N: usize> VecInput<N> {
+    pub fn view(&self, value: [f32; N]) -> Element<'_, Message> {
+        let fields = (0..N).map(|axis| {
+            let content = match &self.draft {
+                Some(draft) if draft.axis == axis && draft.value == value[axis] => {
+                    draft.text.clone()
+                }
+                _ => value[axis].to_string(),
+            };
+
+            Row::new()
+                .push(Scrub {
+                    content: text(self.labels[axis]).into(),
+                    axis,
+                    value: value[axis],
+                    step: self.step,
+                    range: self.range.clone(),
+                })
+                .push(
+                    text_input("", &content)
+                        .on_input(move |text| Message::Typed(axis, text))
+                        .on_submit(Message::Finished),
+                )
+                .spacing(4)
+                .align_items(Alignment::Center)
+                .width(Length::Fill)
+                .into()
+        });
+
+        let fields = Row::with_children(fields)
+            .push_maybe((!self.unit.is_empty()).then(|| text(self.unit)))
+            .spacing(8)
+            .align_items(Alignment::Center);
+
+        Column::new()
+            .push_maybe(self.title.map(text))
+            .push(fields)
+            .spacing(2)
+            .into()
+    }
+}
+
+impl<const N: usize> VecInput<N> {
+    #[must_use]
+    pub fn update(&mut self, message: Message, value: [f32; N]) -> Action<N> {
+        match message {
+            Message::Typed(axis, text) => {
+                let parsed = text
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|parsed| parsed.is_finite())
+                    .map(|parsed| parsed.clamp(*self.range.start(), *self.range.end()));
+                self.draft = Some(Draft {
+                    axis,
+                    text,
+                    value: parsed.unwrap_or(value[axis]),
+                });
+
+                match parsed {
+                    Some(parsed) => {
+                        let mut value = value;
+                        value[axis] = parsed;
+                        Action::Change(value)
+                    }
+                    None => Action::None,
+                }
+            }
+            Message::Scrubbed(axis, scrubbed) => {
+                self.draft = None;
+                let mut value = value;
+                value[axis] = scrubbed;
+                Action::Change(value)
+            }
+            Message::Finished => {
+                self.draft = None;
+                Action::Finish
+            }
+        }
+    }
+}
+
+/// An axis label that scrubs its value while dragged.
+struct Scrub<'a> {
+    content: Element<'a, Message>,
+    axis: usize,
+    value: f32,
+    step: f32,
+    range: RangeInclusive<f32>,
+}
+
+#[derive(Default)]
+struct ScrubState {
+    /// Cursor position and value when the drag started, or last changed speed
+    drag: Option<(f32, f32)>,
+    modifiers: keyboard::Modifiers,
+}
+
+impl Scrub<'_> {
+    /// Value with the cursor at `x`, rounded to the precision of the step so it reads cleanly.
+    fn scrubbed(&self, state: &ScrubState, x: f32) -> Option<f32> {
+        let (start_x, start_value) = state.drag?;
+        let step = if state.modifiers.shift() {
+            self.step * 0.1
+        } else {
+            self.step
+        };
+
+        let decimals = (-step.log10()).ceil().max(0.0) as i32;
+        let precision = 10f32.powi(decimals);
+        let value = start_value + (x - start_x) * step;
+        let value =
+            ((value * precision).round() / precision).clamp(*self.range.start(), *self.range.end());
+
+        Some(value)
+    }
+}
+
+impl Widget<Message, Theme, Renderer> for Scrub<'_> {
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<ScrubState>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(ScrubState::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn size(&self) -> Size<Length> {
+        self.content.as_widget().size()
+    }
+
+    fn layout(
+        &self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        self.content
+            .as_widget()
+            .layout(&mut tree.children[0], renderer, limits)
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<ScrubState>();
+
+        match event {
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                // Carry on from where the drag is at the new speed, rather than jumping
+                if state.drag.is_some() {
+                    if let Some(position) = cursor.position() {
+                        state.drag = Some((position.x, self.value));
+                    }
+                }
+                state.modifiers = modifiers;
+                event::Status::Ignored
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                match cursor.position_over(layout.bounds()) {
+                    Some(position) => {
+                        state.drag = Some((position.x, self.value));
+                        event::Status::Captured
+                    }
+                    None => event::Status::Ignored,
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                match self.scrubbed(state, position.x) {
+                    Some(value) => {
+                        if value != self.value {
+                            shell.publish(Message::Scrubbed(self.axis, value));
+                        }
+                        event::Status::Captured
+                    }
+                    None => event::Status::Ignored,
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.drag.is_some() =>
+            {
+                state.drag = None;
+                shell.publish(Message::Finished);
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let state = tree.state.downcast_ref::<ScrubState>();
+        if state.drag.is_some() || cursor.is_over(layout.bounds()) {
+            mouse::Interaction::ResizingHorizontally
+        } else {
+            mouse::Interaction::Idle
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.content.as_widget().draw(
+            &tree.children[0],
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor,
+            viewport,
+        );
+    }
+}
+
+impl<'a> From<Scrub<'a>> for Element<'a, Message> {
+    fn from(scrub: Scrub<'a>) -> Self {
+        Element::new(scrub)
+    }
+}